@@ -8,7 +8,7 @@ use crate::proxy::net::bridge::get_default_interface;
 
 use crate::raw_config::{RawConfig, RawRole};
 
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Config {
     pub proxy_config: ProxyRawConfig,
 }
@@ -1,7 +1,7 @@
 use chaos_tproxy_proxy::raw_config::{RawRule, TLSRawConfig};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Eq, PartialEq, Clone, Deserialize, Serialize, Default)]
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize, Default)]
 #[serde(deny_unknown_fields)] // To prevent typos.
 pub struct RawConfig {
     pub proxy_ports: Option<Vec<u16>>,
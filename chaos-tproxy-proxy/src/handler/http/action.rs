@@ -1,24 +1,517 @@
+use std::cmp::min;
 use std::collections::HashMap;
+use std::io;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use anyhow::anyhow;
-use futures::TryStreamExt;
-use http::header::HeaderMap;
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::{future, stream, StreamExt};
+use http::header::{
+    HeaderMap, HeaderName, HeaderValue, CONTENT_LENGTH, LOCATION, TRANSFER_ENCODING,
+};
 use http::{Method, Request, Response, StatusCode, Uri};
+use hyper::body::HttpBody;
 use hyper::Body;
+use json_patch::{Patch, PatchError};
+use rand::rngs::StdRng;
+use rand::{thread_rng, Rng, SeedableRng};
+use regex::bytes::Regex;
 use serde_json::Value;
-use tokio::time::sleep;
+use tokio::time::{sleep, Instant};
 use tracing::{debug, instrument};
+use wildmatch::WildMatch;
 
-#[derive(Debug, Eq, PartialEq, Clone)]
+use crate::handler::http::cookie::{edit_request_cookies, edit_set_cookies};
+use crate::handler::http::encoding::{decode_body, encode_body};
+
+#[derive(Debug, PartialEq, Clone, Default)]
 pub struct Actions {
+    pub probability: Option<Probability>,
     pub abort: bool,
-    pub delay: Option<Duration>,
+    pub delay: Option<DelayAction>,
     pub replace: Option<ReplaceAction>,
     pub patch: Option<PatchAction>,
+    pub throttle: Option<ThrottleAction>,
+    pub trickle: Option<TrickleAction>,
+    pub stall: Option<StallAction>,
+    pub close: Option<CloseAction>,
+    /// remove headers whose names match any of the patterns.
+    pub remove_headers: Option<Vec<WildMatch>>,
+    /// rename headers from the first name to the second one.
+    pub rename_headers: Option<Vec<(HeaderName, HeaderName)>>,
+    pub cookies: Option<CookieAction>,
+    /// respond with the mocked response, the request would not be forwarded.
+    pub respond: Option<RespondAction>,
+    /// respond with a status code chosen by weights, exclusive with `respond`.
+    pub status_codes: Option<StatusCodesAction>,
+    /// redirect the request, exclusive with `respond` and `status_codes`.
+    /// It is only applied on requests, see [RedirectAction::response].
+    pub redirect: Option<RedirectAction>,
+}
+
+/// Probability decides whether the actions would be applied on a matched request/response.
+/// The rng is shared by all clones, so a seeded one produces a reproducible sequence of decisions.
+#[derive(Debug, Clone)]
+pub struct Probability {
+    /// percent is the chance to apply the actions, in `[0, 100]`.
+    pub percent: f64,
+    pub seed: Option<u64>,
+    rng: Option<Arc<Mutex<StdRng>>>,
+}
+
+impl Probability {
+    pub fn new(percent: f64, seed: Option<u64>) -> Self {
+        Self {
+            percent,
+            seed,
+            rng: seed.map(|seed| Arc::new(Mutex::new(StdRng::seed_from_u64(seed)))),
+        }
+    }
+
+    /// hit rolls the dice, returns true if the actions should be applied.
+    pub fn hit(&self) -> bool {
+        let sample: f64 = match &self.rng {
+            Some(rng) => rng.lock().unwrap().gen_range(0.0..100.0),
+            None => thread_rng().gen_range(0.0..100.0),
+        };
+        sample < self.percent
+    }
+}
+
+impl PartialEq for Probability {
+    fn eq(&self, other: &Self) -> bool {
+        self.percent == other.percent && self.seed == other.seed
+    }
+}
+
+/// MAX_SAMPLED_DELAY caps the delays sampled from distributions, a long tail distribution could
+/// sample a delay long enough to stall the request forever.
+pub const MAX_SAMPLED_DELAY: Duration = Duration::from_secs(3600);
+
+/// DelayAction describes how long the request/response would be delayed, a new duration is sampled
+/// from the distribution every time the action is applied.
+#[derive(Debug, PartialEq, Clone)]
+pub enum DelayAction {
+    Fixed(Duration),
+    /// uniformly distributed in `[latency - jitter, latency + jitter]`, `jitter <= latency`.
+    Jitter {
+        latency: Duration,
+        jitter: Duration,
+    },
+    /// uniformly distributed in `[min, max]`.
+    Uniform {
+        min: Duration,
+        max: Duration,
+    },
+    Normal {
+        mean: Duration,
+        stddev: Duration,
+    },
+    /// long tail distribution with minimum `scale`, smaller `shape` means longer tail.
+    Pareto {
+        scale: Duration,
+        shape: f64,
+    },
+    /// long tail distribution, `median * e^(sigma * N(0, 1))`.
+    LogNormal {
+        median: Duration,
+        sigma: f64,
+    },
+}
+
+impl DelayAction {
+    /// sample returns the duration to delay, samples are truncated into `[0, MAX_SAMPLED_DELAY]`.
+    pub fn sample(&self) -> Duration {
+        let mut rng = thread_rng();
+        let secs = match self {
+            DelayAction::Fixed(delay) => return *delay,
+            DelayAction::Jitter { latency, jitter } => {
+                latency.as_secs_f64() + jitter.as_secs_f64() * rng.gen_range(-1.0..=1.0)
+            }
+            DelayAction::Uniform { min, max } => {
+                rng.gen_range(min.as_secs_f64()..=max.as_secs_f64())
+            }
+            DelayAction::Normal { mean, stddev } => {
+                mean.as_secs_f64() + stddev.as_secs_f64() * standard_normal(&mut rng)
+            }
+            DelayAction::Pareto { scale, shape } => {
+                // inverse transform sampling, `1 - gen()` is in (0, 1].
+                scale.as_secs_f64() / (1.0 - rng.gen::<f64>()).powf(1.0 / shape)
+            }
+            DelayAction::LogNormal { median, sigma } => {
+                median.as_secs_f64() * (sigma * standard_normal(&mut rng)).exp()
+            }
+        };
+        // `max` and `min` also turn NaN into zero
+        Duration::from_secs_f64(secs.max(0.0).min(MAX_SAMPLED_DELAY.as_secs_f64()))
+    }
+}
+
+/// standard_normal samples from N(0, 1) by the Box-Muller transform.
+fn standard_normal<R: Rng>(rng: &mut R) -> f64 {
+    let u1 = 1.0 - rng.gen::<f64>();
+    let u2 = rng.gen::<f64>();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+}
+
+impl Actions {
+    /// hit checks whether the actions should be applied this time.
+    /// It is always true if no [Probability] is given.
+    pub fn hit(&self) -> bool {
+        self.probability.iter().all(Probability::hit)
+    }
+
+    /// mocked_response returns the response of `respond` or `status_codes` if any.
+    pub fn mocked_response(&self) -> Option<Response<Body>> {
+        self.respond
+            .as_ref()
+            .or_else(|| self.status_codes.as_ref().map(StatusCodesAction::sample))
+            .map(RespondAction::response)
+    }
+
+    /// body_test returns the body patch whose `test` operations select the target,
+    /// see [PatchBodyAction::test_as_selector].
+    fn body_test(&self) -> Option<&PatchBodyAction> {
+        self.patch
+            .as_ref()?
+            .body
+            .as_ref()
+            .filter(|body| body.test_as_selector)
+    }
+
+    /// requires_body checks whether the body is required to select the target.
+    pub fn requires_body(&self) -> bool {
+        self.body_test().is_some()
+    }
+
+    /// select_body checks the decoded body with the `test` operations of the body patch.
+    /// `None` is given if the body is not buffered, it's never selected by the tests.
+    pub fn select_body(&self, body: Option<&[u8]>) -> bool {
+        self.body_test().map_or(true, |patch| patch.select(body))
+    }
+}
+
+/// ThrottleAction limits the bandwidth of the body by a token bucket.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct ThrottleAction {
+    /// bytes per second.
+    pub rate: u64,
+    /// max bytes could be sent at once.
+    pub burst: u64,
+}
+
+/// TrickleAction streams the body in chunks of `chunk_size` bytes, pausing `interval` between chunks.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct TrickleAction {
+    pub chunk_size: usize,
+    pub interval: Duration,
 }
 
+/// StallAction sends the first `after_bytes` bytes of the body and then stalls,
+/// the rest of body would be sent after `duration`, or never if `duration` is `None`.
 #[derive(Debug, Eq, PartialEq, Clone)]
+pub struct StallAction {
+    pub after_bytes: usize,
+    pub duration: Option<Duration>,
+}
+
+/// CloseAction tears down the client connection instead of sending a complete response.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum CloseAction {
+    /// send the first `n` bytes of the response body, then close the connection.
+    Truncate(usize),
+    /// close the connection with TCP RST.
+    Reset,
+    /// close the connection before sending any response bytes.
+    Close,
+}
+
+/// CookieAction edits the cookies in the `Cookie` headers of requests,
+/// or the `Set-Cookie` headers of responses.
+#[derive(Debug, Eq, PartialEq, Clone, Default)]
+pub struct CookieAction {
+    /// pairs of name and value to add, the value would be replaced if the cookie exists.
+    pub add: Vec<(String, String)>,
+    /// pairs of name and value to replace, only if the cookie exists.
+    pub replace: Vec<(String, String)>,
+    /// names of cookies to delete.
+    pub delete: Vec<String>,
+}
+
+/// RespondAction is a fully specified response, served without contacting the target.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct RespondAction {
+    pub code: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+impl RespondAction {
+    pub fn response(&self) -> Response<Body> {
+        let mut response = Response::new(Body::from(self.body.clone()));
+        *response.status_mut() = self.code;
+        *response.headers_mut() = self.headers.clone();
+        response
+    }
+}
+
+/// StatusCodesAction chooses one of the responses by the weights.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct StatusCodesAction {
+    /// pairs of weight and the response, weights should be positive.
+    pub choices: Vec<(u32, RespondAction)>,
+}
+
+impl StatusCodesAction {
+    pub fn sample(&self) -> &RespondAction {
+        let total: u32 = self.choices.iter().map(|(weight, _)| weight).sum();
+        let mut n = thread_rng().gen_range(0..total);
+        for (weight, respond) in &self.choices {
+            if n < *weight {
+                return respond;
+            }
+            n -= weight;
+        }
+        unreachable!("sampled out of the total weight")
+    }
+}
+
+/// RedirectAction responds with a redirect instead of forwarding the request.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct RedirectAction {
+    /// one of 301, 302, 307 and 308.
+    pub code: StatusCode,
+    /// template of the `Location` header, `{path}`, `{query}` and `{path_and_query}`
+    /// would be replaced with the parts of the request URI.
+    pub location: String,
+}
+
+impl RedirectAction {
+    /// location renders the template with the request URI, `{query}` is empty without a query.
+    pub fn location(&self, uri: &Uri) -> String {
+        let placeholders = [
+            (
+                "{path_and_query}",
+                uri.path_and_query().map_or("/", |paq| paq.as_str()),
+            ),
+            ("{path}", uri.path()),
+            ("{query}", uri.query().unwrap_or("")),
+        ];
+        // render in a single pass, so the placeholders in the request URI are kept as they are
+        let mut location = String::with_capacity(self.location.len());
+        let mut rest = self.location.as_str();
+        while let Some(start) = rest.find('{') {
+            location.push_str(&rest[..start]);
+            rest = &rest[start..];
+            match placeholders.iter().find(|(key, _)| rest.starts_with(key)) {
+                Some((key, value)) => {
+                    location.push_str(value);
+                    rest = &rest[key.len()..];
+                }
+                None => {
+                    location.push('{');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        location.push_str(rest);
+        location
+    }
+
+    /// response returns the redirect to the location rendered with the request URI.
+    pub fn response(&self, uri: &Uri) -> anyhow::Result<Response<Body>> {
+        Ok(Response::builder()
+            .status(self.code)
+            .header(LOCATION, self.location(uri))
+            .header(CONTENT_LENGTH, 0)
+            .body(Body::empty())?)
+    }
+}
+
+/// truncate_body streams at most `n` bytes of the body, then fails the stream to make hyper
+/// close the connection.
+pub fn truncate_body(body: Body, n: usize) -> Body {
+    let truncated = || io::Error::new(io::ErrorKind::Other, "body truncated");
+    Body::wrap_stream(stream::unfold(Some((body, n)), move |state| async move {
+        let (mut body, rest) = state?;
+        if rest == 0 {
+            return Some((Err(truncated()), None));
+        }
+        match body.next().await {
+            Some(Ok(mut chunk)) => {
+                chunk.truncate(rest);
+                let rest = rest - chunk.len();
+                Some((Ok(chunk), Some((body, rest))))
+            }
+            Some(Err(e)) => Some((Err(io::Error::new(io::ErrorKind::Other, e)), None)),
+            None => Some((Err(truncated()), None)),
+        }
+    }))
+}
+
+/// Pacer controls how a body is streamed, see [paced_body].
+#[async_trait]
+trait Pacer: Send + 'static {
+    /// chunk_size returns the max size of the next chunk.
+    fn chunk_size(&self) -> usize;
+
+    /// wait would be awaited before sending a chunk of `len` bytes.
+    async fn wait(&mut self, len: usize);
+
+    /// finish would be awaited after the whole body is sent.
+    async fn finish(&mut self) {}
+}
+
+/// paced_body re-chunks the body and streams it under the control of the pacer,
+/// the body would not be buffered.
+fn paced_body<P: Pacer>(body: Body, pacer: P) -> Body {
+    let state = (body, Bytes::new(), pacer);
+    Body::wrap_stream(stream::unfold(
+        state,
+        |(mut body, mut pending, mut pacer)| async move {
+            if pending.is_empty() {
+                match body.next().await {
+                    Some(Ok(chunk)) => pending = chunk,
+                    Some(Err(e)) => return Some((Err(e), (body, pending, pacer))),
+                    None => {
+                        pacer.finish().await;
+                        return None;
+                    }
+                }
+            }
+            let chunk = pending.split_to(min(pending.len(), pacer.chunk_size()));
+            pacer.wait(chunk.len()).await;
+            Some((Ok(chunk), (body, pending, pacer)))
+        },
+    ))
+}
+
+/// TokenBucket is filled with `rate` tokens per second, holding `burst` tokens at most.
+struct TokenBucket {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    fn new(throttle: &ThrottleAction) -> Self {
+        Self {
+            rate: throttle.rate as f64,
+            burst: throttle.burst as f64,
+            tokens: throttle.burst as f64,
+            last: Instant::now(),
+        }
+    }
+}
+
+#[async_trait]
+impl Pacer for TokenBucket {
+    fn chunk_size(&self) -> usize {
+        self.burst as usize
+    }
+
+    /// wait until `len` tokens are available.
+    async fn wait(&mut self, len: usize) {
+        let now = Instant::now();
+        let refill = now.duration_since(self.last).as_secs_f64() * self.rate;
+        self.tokens = (self.tokens + refill).min(self.burst) - len as f64;
+        self.last = now;
+        if self.tokens < 0.0 {
+            sleep(Duration::from_secs_f64(-self.tokens / self.rate)).await
+        }
+    }
+}
+
+struct Trickle {
+    action: TrickleAction,
+    started: bool,
+}
+
+#[async_trait]
+impl Pacer for Trickle {
+    fn chunk_size(&self) -> usize {
+        self.action.chunk_size
+    }
+
+    async fn wait(&mut self, _: usize) {
+        if self.started {
+            sleep(self.action.interval).await
+        }
+        self.started = true;
+    }
+}
+
+struct Stall {
+    action: StallAction,
+    sent: usize,
+    stalled: bool,
+}
+
+impl Stall {
+    async fn stall(&mut self) {
+        if !self.stalled {
+            self.stalled = true;
+            match self.action.duration {
+                Some(duration) => sleep(duration).await,
+                None => futures::future::pending().await,
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Pacer for Stall {
+    fn chunk_size(&self) -> usize {
+        match self.action.after_bytes.checked_sub(self.sent) {
+            Some(rest) if rest > 0 => rest,
+            _ => usize::MAX,
+        }
+    }
+
+    async fn wait(&mut self, len: usize) {
+        if self.sent >= self.action.after_bytes {
+            self.stall().await;
+        }
+        self.sent += len;
+    }
+
+    /// stall before the end of body even if the body is shorter than `after_bytes`.
+    async fn finish(&mut self) {
+        self.stall().await;
+    }
+}
+
+/// stream_body applies the actions which re-stream the body.
+fn stream_body(mut body: Body, actions: &Actions) -> Body {
+    if let Some(throttle) = &actions.throttle {
+        body = paced_body(body, TokenBucket::new(throttle));
+    }
+    if let Some(trickle) = &actions.trickle {
+        body = paced_body(
+            body,
+            Trickle {
+                action: trickle.clone(),
+                started: false,
+            },
+        );
+    }
+    if let Some(stall) = &actions.stall {
+        body = paced_body(
+            body,
+            Stall {
+                action: stall.clone(),
+                sent: 0,
+                stalled: false,
+            },
+        );
+    }
+    body
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub struct PatchAction {
     pub body: Option<PatchBodyAction>,
     pub queries: Option<String>,
@@ -38,27 +531,154 @@ pub struct ReplaceAction {
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub struct ReplaceBodyAction {
     pub contents: Vec<u8>,
+    /// set `Content-Length` to the length of new body, or keep the original one if false.
+    pub update_content_length: bool,
 }
 
-#[derive(Debug, Eq, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct PatchBodyAction {
     pub contents: PatchBodyActionContents,
+    /// set `Content-Length` to the length of new body, or keep the original one if false.
+    pub update_content_length: bool,
+    /// `test` operations of the JSON patch select the target, the rule is skipped if any of them
+    /// fails on the body before any action is applied.
+    pub test_as_selector: bool,
 }
 
-#[derive(Debug, Eq, PartialEq, Clone)]
+impl PatchBodyAction {
+    /// select checks the decoded body with the `test` operations of the JSON patch, the body is
+    /// selected unless a `test` fails. Other patches select any body.
+    pub fn select(&self, body: Option<&[u8]>) -> bool {
+        let patch = match &self.contents {
+            PatchBodyActionContents::JSONPatch(patch) => patch,
+            _ => return true,
+        };
+        let mut value: Value = match body.and_then(|body| serde_json::from_slice(body).ok()) {
+            Some(value) => value,
+            None => return false,
+        };
+        !matches!(
+            json_patch::patch(&mut value, patch),
+            Err(PatchError::TestFailed)
+        )
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub enum PatchBodyActionContents {
+    /// JSON merge patch as [rfc7396](https://tools.ietf.org/html/rfc7396).
     JSON(Value),
+    /// JSON patch as [rfc6902](https://tools.ietf.org/html/rfc6902).
+    JSONPatch(Patch),
+    /// replace all matches of the regex with the replacement, capture groups could be
+    /// referred in the replacement as `$1` or `${name}`.
+    Regex {
+        regex: BodyRegex,
+        replacement: Vec<u8>,
+    },
+}
+
+/// BodyRegex is a regex on bytes, two regexes are equal if their patterns are equal.
+#[derive(Debug, Clone)]
+pub struct BodyRegex(pub Regex);
+
+impl PartialEq for BodyRegex {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+impl Eq for BodyRegex {}
+
+/// set_content_length sets `Content-Length` for a body of fixed length, chunked transfer encoding
+/// is no longer needed.
+fn set_content_length(headers: &mut HeaderMap, len: usize) {
+    headers.remove(TRANSFER_ENCODING);
+    headers.insert(CONTENT_LENGTH, HeaderValue::from(len));
+}
+
+/// read_body buffers the whole body.
+async fn read_body(body: Body) -> anyhow::Result<Vec<u8>> {
+    read_body_within(body, usize::MAX)
+        .await?
+        .map_err(|_| anyhow!("body is larger than {} bytes", usize::MAX))
+}
+
+/// read_body_within is [read_body] stopping once the body is larger than `limit`. The body is
+/// returned back as `Err` in that case, with the chunks read ahead of the rest.
+pub async fn read_body_within(
+    mut body: Body,
+    limit: usize,
+) -> anyhow::Result<Result<Vec<u8>, Body>> {
+    let mut data = vec![];
+    while let Some(chunk) = body.data().await {
+        data.extend_from_slice(&chunk?);
+        if data.len() > limit {
+            let read = stream::once(future::ok(Bytes::from(data)));
+            return Ok(Err(Body::wrap_stream(read.chain(body))));
+        }
+    }
+    Ok(Ok(data))
+}
+
+/// patch_body buffers the body and applies the body patch on it.
+/// The body would be decoded before patching and encoded again as `Content-Encoding`.
+async fn patch_body(
+    body: Body,
+    headers: &mut HeaderMap,
+    action: &PatchBodyAction,
+) -> anyhow::Result<Body> {
+    let raw = read_body(body).await?;
+    let data = decode_body(headers, raw.clone())?;
+    let patched = match &action.contents {
+        PatchBodyActionContents::JSON(merge) => {
+            let mut value: Value = serde_json::from_slice(&data)?;
+            json_patch::merge(&mut value, merge);
+            serde_json::to_vec(&value)?
+        }
+        PatchBodyActionContents::JSONPatch(patch) => {
+            let mut value: Value = serde_json::from_slice(&data)?;
+            match json_patch::patch(&mut value, patch) {
+                // the body may be changed by other rules after it's selected
+                Err(PatchError::TestFailed) if action.test_as_selector => return Ok(raw.into()),
+                result => result?,
+            }
+            serde_json::to_vec(&value)?
+        }
+        PatchBodyActionContents::Regex { regex, replacement } => regex
+            .0
+            .replace_all(&data, replacement.as_slice())
+            .into_owned(),
+    };
+    let patched = encode_body(headers, patched)?;
+    if action.update_content_length {
+        set_content_length(headers, patched.len());
+    }
+    Ok(patched.into())
 }
 
-async fn read_value(body: &mut Body) -> anyhow::Result<Value> {
-    let tmp = std::mem::take(body);
-    let data: Vec<u8> = tmp
-        .try_fold(vec![], |mut data, seg| {
-            data.extend(seg);
-            futures::future::ok(data)
-        })
-        .await?;
-    Ok(serde_json::from_slice(&data)?)
+/// edit_headers removes and renames headers as the actions.
+fn edit_headers(headers: &mut HeaderMap, actions: &Actions) {
+    if let Some(patterns) = &actions.remove_headers {
+        let names: Vec<HeaderName> = headers
+            .keys()
+            .filter(|name| patterns.iter().any(|p| p.matches(name.as_str())))
+            .cloned()
+            .collect();
+        for name in names {
+            headers.remove(name);
+        }
+    }
+
+    if let Some(renames) = &actions.rename_headers {
+        for (from, to) in renames {
+            let values: Vec<HeaderValue> = headers.get_all(from).iter().cloned().collect();
+            headers.remove(from);
+            for value in values {
+                headers.append(to, value);
+            }
+        }
+    }
 }
 
 /// apply_request_action would inject chaos actions into the given request.
@@ -74,8 +694,16 @@ pub async fn apply_request_action(
     }
 
     // delay the request
-    if let Some(delay) = actions.delay {
-        sleep(delay).await
+    if let Some(delay) = &actions.delay {
+        sleep(delay.sample()).await
+    }
+
+    // remove and rename the request headers
+    edit_headers(request.headers_mut(), actions);
+
+    // edit the request cookies
+    if let Some(cookies) = &actions.cookies {
+        edit_request_cookies(request.headers_mut(), cookies)?;
     }
 
     if let Some(replace) = &actions.replace {
@@ -90,7 +718,9 @@ pub async fn apply_request_action(
         if let Some(body) = &replace.body {
             // replace the request body
             *request.body_mut() = body.contents.clone().into();
-            request.headers_mut().remove(http::header::CONTENT_LENGTH);
+            if body.update_content_length {
+                set_content_length(request.headers_mut(), body.contents.len());
+            }
         }
 
         // replace request query parameters
@@ -108,14 +738,10 @@ pub async fn apply_request_action(
         // append request query parameters
         append_queries(request.uri_mut(), patch.queries.as_ref())?;
 
-        // patch request body with JSON Patch
-        if let Some(patch_body) = &patch.body {
-            let PatchBodyActionContents::JSON(ref value) = patch_body.contents;
-            let mut data = read_value(request.body_mut()).await?;
-            json_patch::merge(&mut data, value);
-            let merged = serde_json::to_vec(&data)?;
-            *request.body_mut() = merged.into();
-            request.headers_mut().remove(http::header::CONTENT_LENGTH);
+        // patch request body
+        if let Some(action) = &patch.body {
+            let body = std::mem::take(request.body_mut());
+            *request.body_mut() = patch_body(body, request.headers_mut(), action).await?;
         }
 
         // patch headers
@@ -126,6 +752,10 @@ pub async fn apply_request_action(
         }
     }
 
+    // throttle, trickle or stall the request body
+    let body = std::mem::take(request.body_mut());
+    *request.body_mut() = stream_body(body, actions);
+
     debug!("action applied: {:?}", request);
     Ok(request)
 }
@@ -209,8 +839,21 @@ pub async fn apply_response_action(
     }
 
     // delay the response
-    if let Some(delay) = actions.delay {
-        sleep(delay).await
+    if let Some(delay) = &actions.delay {
+        sleep(delay.sample()).await
+    }
+
+    // replace the whole response with the mocked one
+    if let Some(mocked) = actions.mocked_response() {
+        response = mocked;
+    }
+
+    // remove and rename the response headers
+    edit_headers(response.headers_mut(), actions);
+
+    // edit the cookies set by the response
+    if let Some(cookies) = &actions.cookies {
+        edit_set_cookies(response.headers_mut(), cookies)?;
     }
 
     if let Some(replace) = &actions.replace {
@@ -222,7 +865,9 @@ pub async fn apply_response_action(
         // replace the response body
         if let Some(body) = &replace.body {
             *response.body_mut() = body.contents.clone().into();
-            response.headers_mut().remove(http::header::CONTENT_LENGTH);
+            if body.update_content_length {
+                set_content_length(response.headers_mut(), body.contents.len());
+            }
         }
 
         // replace the response header
@@ -234,14 +879,10 @@ pub async fn apply_response_action(
     }
 
     if let Some(patch) = &actions.patch {
-        // patch response body with JSON Patch
-        if let Some(patch_body) = &patch.body {
-            let PatchBodyActionContents::JSON(ref value) = patch_body.contents;
-            let mut data = read_value(response.body_mut()).await?;
-            json_patch::merge(&mut data, value);
-            let merged = serde_json::to_vec(&data)?;
-            *response.body_mut() = merged.into();
-            response.headers_mut().remove(http::header::CONTENT_LENGTH);
+        // patch response body
+        if let Some(action) = &patch.body {
+            let body = std::mem::take(response.body_mut());
+            *response.body_mut() = patch_body(body, response.headers_mut(), action).await?;
         }
         // patch headers
         if let Some(hdrs) = &patch.headers {
@@ -251,13 +892,33 @@ pub async fn apply_response_action(
         }
     }
 
+    // throttle, trickle or stall the response body
+    let body = std::mem::take(response.body_mut());
+    *response.body_mut() = stream_body(body, actions);
+
     debug!("action applied: {:?}", response);
     Ok(response)
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::handler::http::action::{append_queries, replace_path};
+    use std::time::Duration;
+
+    use futures::StreamExt;
+    use http::header::{HeaderMap, CONTENT_LENGTH, CONTENT_TYPE, LOCATION};
+    use http::{Request, Response, StatusCode};
+    use hyper::Body;
+    use regex::bytes::Regex;
+    use tokio::time::Instant;
+    use wildmatch::WildMatch;
+
+    use crate::handler::http::action::{
+        append_queries, apply_request_action, apply_response_action, edit_headers, paced_body,
+        patch_body, replace_path, truncate_body, Actions, BodyRegex, DelayAction, PatchAction,
+        PatchBodyAction, PatchBodyActionContents, Probability, RedirectAction, ReplaceAction,
+        ReplaceBodyAction, RespondAction, Stall, StallAction, StatusCodesAction, ThrottleAction,
+        TokenBucket, Trickle, TrickleAction, MAX_SAMPLED_DELAY,
+    };
 
     #[test]
     fn test_append_queries() {
@@ -298,6 +959,326 @@ mod tests {
         assert_eq!(&uri.to_string(), "https://hyper.rs/hhh?a=b");
     }
 
+    #[test]
+    fn test_probability() {
+        assert!(Probability::new(100.0, None).hit());
+        assert!(!Probability::new(0.0, None).hit());
+
+        let left = Probability::new(50.0, Some(42));
+        let right = Probability::new(50.0, Some(42));
+        let hits: Vec<_> = (0..64).map(|_| left.hit()).collect();
+        assert_eq!(hits, (0..64).map(|_| right.hit()).collect::<Vec<_>>());
+        assert!(hits.iter().any(|hit| *hit));
+        assert!(hits.iter().any(|hit| !*hit));
+    }
+
+    #[test]
+    fn test_delay_sample() {
+        let ms = Duration::from_millis;
+        assert_eq!(DelayAction::Fixed(ms(10)).sample(), ms(10));
+
+        for _ in 0..64 {
+            let delay = DelayAction::Jitter {
+                latency: ms(100),
+                jitter: ms(20),
+            }
+            .sample();
+            assert!(delay >= ms(80) && delay <= ms(120));
+
+            let delay = DelayAction::Uniform {
+                min: ms(10),
+                max: ms(20),
+            }
+            .sample();
+            assert!(delay >= ms(10) && delay <= ms(20));
+
+            let delay = DelayAction::Pareto {
+                scale: ms(10),
+                shape: 1.5,
+            }
+            .sample();
+            assert!(delay >= ms(10));
+
+            let delay = DelayAction::Pareto {
+                scale: ms(10),
+                shape: 0.01,
+            }
+            .sample();
+            assert!(delay <= MAX_SAMPLED_DELAY);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_throttle_body() {
+        let throttle = ThrottleAction {
+            rate: 1000,
+            burst: 100,
+        };
+        let start = Instant::now();
+        let body = paced_body(Body::from(vec![0u8; 300]), TokenBucket::new(&throttle));
+        let data = hyper::body::to_bytes(body).await.unwrap();
+        assert_eq!(data.len(), 300);
+        assert!(start.elapsed() >= Duration::from_millis(190));
+    }
+
+    #[tokio::test]
+    async fn test_trickle_body() {
+        let trickle = Trickle {
+            action: TrickleAction {
+                chunk_size: 2,
+                interval: Duration::from_millis(50),
+            },
+            started: false,
+        };
+        let start = Instant::now();
+        let chunks: Vec<_> = paced_body(Body::from("abcdef"), trickle)
+            .map(Result::unwrap)
+            .collect()
+            .await;
+        assert_eq!(chunks, vec!["ab", "cd", "ef"]);
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn test_stall_body() {
+        let stall = Stall {
+            action: StallAction {
+                after_bytes: 4,
+                duration: None,
+            },
+            sent: 0,
+            stalled: false,
+        };
+        let mut body = paced_body(Body::from("abcdef"), stall);
+        assert_eq!(body.next().await.unwrap().unwrap(), "abcd");
+        let rest = tokio::time::timeout(Duration::from_millis(100), body.next()).await;
+        assert!(rest.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_truncate_body() {
+        let mut body = truncate_body(Body::from("abcdef"), 4);
+        assert_eq!(body.next().await.unwrap().unwrap(), "abcd");
+        assert!(body.next().await.unwrap().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_update_content_length() {
+        let request = || {
+            Request::builder()
+                .header(CONTENT_LENGTH, 5)
+                .body(Body::from("hello"))
+                .unwrap()
+        };
+        let mut actions = Actions {
+            replace: Some(ReplaceAction {
+                path: None,
+                method: None,
+                body: Some(ReplaceBodyAction {
+                    contents: b"hi".to_vec(),
+                    update_content_length: true,
+                }),
+                code: None,
+                queries: None,
+                headers: None,
+            }),
+            ..Default::default()
+        };
+        let req = apply_request_action(request(), &actions).await.unwrap();
+        assert_eq!(req.headers()[CONTENT_LENGTH], "2");
+
+        if let Some(body) = actions.replace.as_mut().and_then(|r| r.body.as_mut()) {
+            body.update_content_length = false;
+        }
+        let req = apply_request_action(request(), &actions).await.unwrap();
+        assert_eq!(req.headers()[CONTENT_LENGTH], "5");
+    }
+
+    #[tokio::test]
+    async fn test_patch_body() {
+        let patch = serde_json::from_str(
+            r#"[{"op": "test", "path": "/kind", "value": "list"}, {"op": "remove", "path": "/items/0"}]"#,
+        )
+        .unwrap();
+        let mut action = PatchBodyAction {
+            contents: PatchBodyActionContents::JSONPatch(patch),
+            update_content_length: true,
+            test_as_selector: true,
+        };
+        let mut headers = HeaderMap::new();
+
+        let body = Body::from(r#"{"kind":"list","items":[1,2]}"#);
+        let body = patch_body(body, &mut headers, &action).await.unwrap();
+        let data = hyper::body::to_bytes(body).await.unwrap();
+        assert_eq!(data, r#"{"items":[2],"kind":"list"}"#);
+        assert_eq!(headers[CONTENT_LENGTH], "27");
+
+        let body = Body::from(r#"{"kind":"map","items":[1,2]}"#);
+        let body = patch_body(body, &mut headers, &action).await.unwrap();
+        let data = hyper::body::to_bytes(body).await.unwrap();
+        assert_eq!(data, r#"{"kind":"map","items":[1,2]}"#);
+
+        action.test_as_selector = false;
+        let body = Body::from(r#"{"kind":"map","items":[1,2]}"#);
+        assert!(patch_body(body, &mut headers, &action).await.is_err());
+    }
+
+    #[test]
+    fn test_select_body() {
+        let patch = serde_json::from_str(
+            r#"[{"op": "test", "path": "/kind", "value": "list"}, {"op": "remove", "path": "/items/0"}]"#,
+        )
+        .unwrap();
+        let mut actions = Actions {
+            patch: Some(PatchAction {
+                body: Some(PatchBodyAction {
+                    contents: PatchBodyActionContents::JSONPatch(patch),
+                    update_content_length: true,
+                    test_as_selector: true,
+                }),
+                queries: None,
+                headers: None,
+            }),
+            ..Default::default()
+        };
+        assert!(actions.requires_body());
+        assert!(actions.select_body(Some(br#"{"kind":"list","items":[1]}"#)));
+        assert!(!actions.select_body(Some(br#"{"kind":"map","items":[1]}"#)));
+        // the body is not buffered or not JSON
+        assert!(!actions.select_body(None));
+        assert!(!actions.select_body(Some(b"kind=list")));
+        // only a failed `test` skips the body
+        assert!(actions.select_body(Some(br#"{"kind":"list","items":[]}"#)));
+
+        actions
+            .patch
+            .as_mut()
+            .unwrap()
+            .body
+            .as_mut()
+            .unwrap()
+            .test_as_selector = false;
+        assert!(!actions.requires_body());
+        assert!(actions.select_body(None));
+    }
+
+    #[tokio::test]
+    async fn test_patch_body_regex() {
+        let action = PatchBodyAction {
+            contents: PatchBodyActionContents::Regex {
+                regex: BodyRegex(Regex::new(r"<b>(\w+)</b>").unwrap()),
+                replacement: b"<i>$1</i>".to_vec(),
+            },
+            update_content_length: false,
+            test_as_selector: false,
+        };
+        let body = Body::from("<p><b>hello</b> <b>world</b></p>");
+        let body = patch_body(body, &mut HeaderMap::new(), &action)
+            .await
+            .unwrap();
+        let data = hyper::body::to_bytes(body).await.unwrap();
+        assert_eq!(data, "<p><i>hello</i> <i>world</i></p>");
+    }
+
+    #[test]
+    fn test_edit_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert("authorization", "token".parse().unwrap());
+        headers.insert("x-cors-origin", "*".parse().unwrap());
+        headers.insert("x-cors-methods", "GET".parse().unwrap());
+        headers.append("x-old", "a".parse().unwrap());
+        headers.append("x-old", "b".parse().unwrap());
+        let actions = Actions {
+            remove_headers: Some(vec![WildMatch::new("x-cors-*")]),
+            rename_headers: Some(vec![
+                ("x-old".parse().unwrap(), "x-new".parse().unwrap()),
+                ("authorization".parse().unwrap(), "x-auth".parse().unwrap()),
+                // renames are applied in order
+                ("x-auth".parse().unwrap(), "x-token".parse().unwrap()),
+            ]),
+            ..Default::default()
+        };
+        edit_headers(&mut headers, &actions);
+        assert_eq!(headers.len(), 3);
+        assert_eq!(headers.get_all("x-new").iter().count(), 2);
+        assert!(!headers.contains_key("x-auth"));
+        assert_eq!(headers["x-token"], "token");
+    }
+
+    #[tokio::test]
+    async fn test_respond() {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, "application/json".parse().unwrap());
+        let actions = Actions {
+            respond: Some(RespondAction {
+                code: StatusCode::SERVICE_UNAVAILABLE,
+                headers,
+                body: r#"{"error":"mocked"}"#.into(),
+            }),
+            ..Default::default()
+        };
+        let upstream = Response::builder()
+            .header("x-upstream", "1")
+            .body(Body::from("upstream"))
+            .unwrap();
+        let response = apply_response_action(upstream, &actions).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers().len(), 1);
+        assert_eq!(response.headers()[CONTENT_TYPE], "application/json");
+        let data = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(data, r#"{"error":"mocked"}"#);
+    }
+
+    #[test]
+    fn test_status_codes_sample() {
+        let respond = |code| RespondAction {
+            code,
+            headers: HeaderMap::new(),
+            body: Default::default(),
+        };
+        let action = StatusCodesAction {
+            choices: vec![
+                (70, respond(StatusCode::SERVICE_UNAVAILABLE)),
+                (20, respond(StatusCode::INTERNAL_SERVER_ERROR)),
+                (10, respond(StatusCode::TOO_MANY_REQUESTS)),
+            ],
+        };
+        let mut counts = [0; 3];
+        for _ in 0..10000 {
+            match action.sample().code {
+                StatusCode::SERVICE_UNAVAILABLE => counts[0] += 1,
+                StatusCode::INTERNAL_SERVER_ERROR => counts[1] += 1,
+                StatusCode::TOO_MANY_REQUESTS => counts[2] += 1,
+                code => panic!("unexpected code {}", code),
+            }
+        }
+        assert!((6500..7500).contains(&counts[0]), "{:?}", counts);
+        assert!((1500..2500).contains(&counts[1]), "{:?}", counts);
+        assert!((500..1500).contains(&counts[2]), "{:?}", counts);
+    }
+
+    #[test]
+    fn test_redirect() {
+        let action = RedirectAction {
+            code: StatusCode::TEMPORARY_REDIRECT,
+            location: "https://example.com/v2{path}?{query}&from={path_and_query}{host}"
+                .to_string(),
+        };
+        let uri = "/api/users?id=1".parse().unwrap();
+        let response = action.response(&uri).unwrap();
+        assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+        assert_eq!(
+            response.headers()[LOCATION],
+            "https://example.com/v2/api/users?id=1&from=/api/users?id=1{host}"
+        );
+
+        let action = RedirectAction {
+            code: StatusCode::FOUND,
+            location: "{path}".to_string(),
+        };
+        assert_eq!(action.location(&"/{query}".parse().unwrap()), "/{query}");
+    }
+
     #[test]
     fn test_replace_queries() {
         //todo
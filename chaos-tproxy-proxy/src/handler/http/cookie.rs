@@ -0,0 +1,177 @@
+use http::header::{HeaderMap, HeaderValue, COOKIE, SET_COOKIE};
+
+use crate::handler::http::action::CookieAction;
+
+/// request_cookies parses the name-value pairs from all the `Cookie` headers.
+pub fn request_cookies(headers: &HeaderMap) -> Vec<(&str, &str)> {
+    headers
+        .get_all(COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| match pair.split_once('=') {
+            Some((name, value)) => (name.trim(), value.trim()),
+            None => (pair, ""),
+        })
+        .collect()
+}
+
+/// edit_request_cookies deletes, replaces and adds cookies in the `Cookie` headers,
+/// which would be merged into a single header.
+pub fn edit_request_cookies(headers: &mut HeaderMap, action: &CookieAction) -> anyhow::Result<()> {
+    let mut cookies: Vec<(String, String)> = request_cookies(headers)
+        .into_iter()
+        .filter(|(name, _)| !action.delete.iter().any(|n| n == name))
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+
+    for (name, value) in &action.replace {
+        for cookie in cookies.iter_mut().filter(|(n, _)| n == name) {
+            cookie.1 = value.clone();
+        }
+    }
+
+    for (name, value) in &action.add {
+        match cookies.iter_mut().find(|(n, _)| n == name) {
+            Some(cookie) => cookie.1 = value.clone(),
+            None => cookies.push((name.clone(), value.clone())),
+        }
+    }
+
+    headers.remove(COOKIE);
+    if !cookies.is_empty() {
+        let cookie = cookies
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<_>>()
+            .join("; ");
+        headers.insert(COOKIE, HeaderValue::from_str(&cookie)?);
+    }
+    Ok(())
+}
+
+/// set_cookie_name returns the cookie name of a `Set-Cookie` header value.
+fn set_cookie_name(set_cookie: &str) -> &str {
+    let pair = set_cookie.split(';').next().unwrap_or("");
+    pair.split_once('=').map_or(pair, |(name, _)| name).trim()
+}
+
+/// with_value replaces the cookie value of a `Set-Cookie` header value, keeping the attributes.
+fn with_value(set_cookie: &str, name: &str, value: &str) -> String {
+    match set_cookie.split_once(';') {
+        Some((_, attributes)) => format!("{}={};{}", name, value, attributes),
+        None => format!("{}={}", name, value),
+    }
+}
+
+/// edit_set_cookies deletes, replaces and adds cookies in the `Set-Cookie` headers.
+/// Deleted cookies are expired on the client by `Max-Age=0` with `Path=/`.
+pub fn edit_set_cookies(headers: &mut HeaderMap, action: &CookieAction) -> anyhow::Result<()> {
+    // `Set-Cookie` headers could not be parsed are kept as they are
+    let mut opaque = vec![];
+    let mut set_cookies = vec![];
+    for value in headers.get_all(SET_COOKIE) {
+        match value.to_str() {
+            Ok(set_cookie) => set_cookies.push(set_cookie.to_string()),
+            Err(_) => opaque.push(value.clone()),
+        }
+    }
+
+    set_cookies.retain(|set_cookie| {
+        let name = set_cookie_name(set_cookie);
+        !action.delete.iter().any(|n| n == name)
+    });
+    for name in &action.delete {
+        set_cookies.push(format!("{}=; Path=/; Max-Age=0", name));
+    }
+
+    for (name, value) in &action.replace {
+        for set_cookie in set_cookies.iter_mut() {
+            if set_cookie_name(set_cookie) == name {
+                *set_cookie = with_value(set_cookie, name, value);
+            }
+        }
+    }
+
+    for (name, value) in &action.add {
+        match set_cookies
+            .iter_mut()
+            .find(|set_cookie| set_cookie_name(set_cookie) == name)
+        {
+            Some(set_cookie) => *set_cookie = with_value(set_cookie, name, value),
+            None => set_cookies.push(format!("{}={}", name, value)),
+        }
+    }
+
+    headers.remove(SET_COOKIE);
+    for value in opaque {
+        headers.append(SET_COOKIE, value);
+    }
+    for set_cookie in set_cookies {
+        headers.append(SET_COOKIE, HeaderValue::from_str(&set_cookie)?);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use http::header::{HeaderMap, COOKIE, SET_COOKIE};
+
+    use crate::handler::http::action::CookieAction;
+    use crate::handler::http::cookie::{edit_request_cookies, edit_set_cookies, request_cookies};
+
+    fn action() -> CookieAction {
+        CookieAction {
+            add: vec![
+                ("theme".to_string(), "dark".to_string()),
+                ("lang".to_string(), "en".to_string()),
+            ],
+            replace: vec![
+                ("session".to_string(), "invalid".to_string()),
+                ("missing".to_string(), "x".to_string()),
+            ],
+            delete: vec!["tracking".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_edit_request_cookies() {
+        let mut headers = HeaderMap::new();
+        headers.append(COOKIE, "session=abc; tracking=1".parse().unwrap());
+        headers.append(COOKIE, "theme=light".parse().unwrap());
+        assert_eq!(
+            request_cookies(&headers),
+            vec![("session", "abc"), ("tracking", "1"), ("theme", "light")]
+        );
+
+        edit_request_cookies(&mut headers, &action()).unwrap();
+        assert_eq!(headers.get_all(COOKIE).iter().count(), 1);
+        assert_eq!(headers[COOKIE], "session=invalid; theme=dark; lang=en");
+    }
+
+    #[test]
+    fn test_edit_set_cookies() {
+        let mut headers = HeaderMap::new();
+        headers.append(SET_COOKIE, "session=abc; Path=/; HttpOnly".parse().unwrap());
+        headers.append(SET_COOKIE, "tracking=1; Max-Age=3600".parse().unwrap());
+        headers.append(SET_COOKIE, "theme=light".parse().unwrap());
+
+        edit_set_cookies(&mut headers, &action()).unwrap();
+        let set_cookies: Vec<_> = headers
+            .get_all(SET_COOKIE)
+            .iter()
+            .map(|v| v.to_str().unwrap())
+            .collect();
+        assert_eq!(
+            set_cookies,
+            vec![
+                "session=invalid; Path=/; HttpOnly",
+                "theme=dark",
+                "tracking=; Path=/; Max-Age=0",
+                "lang=en",
+            ]
+        );
+    }
+}
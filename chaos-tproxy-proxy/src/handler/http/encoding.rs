@@ -0,0 +1,142 @@
+use std::io::{Read, Write};
+
+use anyhow::anyhow;
+use flate2::read::{MultiGzDecoder, ZlibDecoder};
+use flate2::write::{GzEncoder, ZlibEncoder};
+use flate2::Compression;
+use http::header::{HeaderMap, CONTENT_ENCODING};
+
+const BROTLI_BUFFER_SIZE: usize = 4096;
+const BROTLI_QUALITY: u32 = 5;
+const BROTLI_LG_WINDOW_SIZE: u32 = 22;
+
+/// Coding is a content coding of `Content-Encoding` supported by body patches.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+enum Coding {
+    Gzip,
+    Deflate,
+    Brotli,
+}
+
+/// codings parses `Content-Encoding` of the headers, in the order they were applied.
+fn codings(headers: &HeaderMap) -> anyhow::Result<Vec<Coding>> {
+    let mut codings = vec![];
+    for value in headers.get_all(CONTENT_ENCODING) {
+        for coding in value.to_str()?.split(',') {
+            match coding.trim().to_ascii_lowercase().as_str() {
+                "" | "identity" => {}
+                "gzip" | "x-gzip" => codings.push(Coding::Gzip),
+                "deflate" => codings.push(Coding::Deflate),
+                "br" => codings.push(Coding::Brotli),
+                other => return Err(anyhow!("unsupported content encoding: {}", other)),
+            }
+        }
+    }
+    Ok(codings)
+}
+
+/// decode_body decodes the body according to `Content-Encoding` of the headers.
+pub fn decode_body(headers: &HeaderMap, data: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+    decode_body_within(headers, data, usize::MAX)
+}
+
+/// decode_body_within is [decode_body] failing once the decoded body is larger than `limit`,
+/// it bounds the memory used to decode a small but highly compressed body.
+pub fn decode_body_within(
+    headers: &HeaderMap,
+    data: Vec<u8>,
+    limit: usize,
+) -> anyhow::Result<Vec<u8>> {
+    // read one more byte to tell whether the decoded body is larger than the limit
+    let max = (limit as u64).saturating_add(1);
+    codings(headers)?
+        .into_iter()
+        .rev()
+        .try_fold(data, |data, coding| {
+            let mut decoded = vec![];
+            match coding {
+                Coding::Gzip => MultiGzDecoder::new(data.as_slice())
+                    .take(max)
+                    .read_to_end(&mut decoded)?,
+                Coding::Deflate => ZlibDecoder::new(data.as_slice())
+                    .take(max)
+                    .read_to_end(&mut decoded)?,
+                Coding::Brotli => brotli::Decompressor::new(data.as_slice(), BROTLI_BUFFER_SIZE)
+                    .take(max)
+                    .read_to_end(&mut decoded)?,
+            };
+            if decoded.len() > limit {
+                return Err(anyhow!("decoded body is larger than {} bytes", limit));
+            }
+            Ok(decoded)
+        })
+}
+
+/// encode_body encodes the body according to `Content-Encoding` of the headers,
+/// it's the inverse of [decode_body].
+pub fn encode_body(headers: &HeaderMap, data: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+    codings(headers)?
+        .into_iter()
+        .try_fold(data, |data, coding| {
+            Ok(match coding {
+                Coding::Gzip => {
+                    let mut encoder = GzEncoder::new(vec![], Compression::default());
+                    encoder.write_all(&data)?;
+                    encoder.finish()?
+                }
+                Coding::Deflate => {
+                    let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+                    encoder.write_all(&data)?;
+                    encoder.finish()?
+                }
+                Coding::Brotli => {
+                    let mut encoder = brotli::CompressorWriter::new(
+                        vec![],
+                        BROTLI_BUFFER_SIZE,
+                        BROTLI_QUALITY,
+                        BROTLI_LG_WINDOW_SIZE,
+                    );
+                    encoder.write_all(&data)?;
+                    encoder.into_inner()
+                }
+            })
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use http::header::{HeaderMap, CONTENT_ENCODING};
+
+    use crate::handler::http::encoding::{decode_body, decode_body_within, encode_body};
+
+    #[test]
+    fn test_encode_decode_body() {
+        let data = br#"{"message": "Hello!"}"#.to_vec();
+        for encoding in ["gzip", "deflate", "br", "gzip, br", "identity"] {
+            let mut headers = HeaderMap::new();
+            headers.insert(CONTENT_ENCODING, encoding.parse().unwrap());
+            let encoded = encode_body(&headers, data.clone()).unwrap();
+            assert_eq!(decode_body(&headers, encoded).unwrap(), data);
+        }
+
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_ENCODING, "zstd".parse().unwrap());
+        assert!(decode_body(&headers, data).is_err());
+    }
+
+    #[test]
+    fn test_decode_body_within() {
+        let data = vec![b'a'; 4096];
+        for encoding in ["gzip", "deflate", "br"] {
+            let mut headers = HeaderMap::new();
+            headers.insert(CONTENT_ENCODING, encoding.parse().unwrap());
+            let encoded = encode_body(&headers, data.clone()).unwrap();
+            assert!(encoded.len() < 1024);
+            assert_eq!(
+                decode_body_within(&headers, encoded.clone(), 4096).unwrap(),
+                data
+            );
+            assert!(decode_body_within(&headers, encoded, 4095).is_err());
+        }
+    }
+}
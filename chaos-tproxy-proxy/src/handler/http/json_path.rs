@@ -0,0 +1,178 @@
+use std::convert::TryFrom;
+
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+
+/// JsonPath is the subset of [JSONPath](https://goessner.net/articles/JsonPath/) used to select
+/// values in the request body. It supports the root `$`, child names `.name` or `['name']`,
+/// array indexes `[0]` (negative ones count from the end), wildcards `.*` or `[*]`,
+/// and descendants `..name`, `..[0]` or `..*`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct JsonPath {
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct Segment {
+    /// the key selects the node and all its descendants instead of the node itself.
+    descendant: bool,
+    key: Key,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Key {
+    Name(String),
+    Index(i64),
+    Wildcard,
+}
+
+impl JsonPath {
+    pub fn parse(path: &str) -> Result<Self> {
+        let mut rest = path
+            .strip_prefix('$')
+            .ok_or_else(|| anyhow!("JSONPath should start with `$`: {}", path))?;
+        let mut segments = vec![];
+        while !rest.is_empty() {
+            let (segment, remaining) =
+                parse_segment(rest).ok_or_else(|| anyhow!("invalid JSONPath: {}", path))?;
+            segments.push(segment);
+            rest = remaining;
+        }
+        Ok(Self { segments })
+    }
+
+    /// query returns all the values selected by the path.
+    pub fn query<'a>(&self, value: &'a Value) -> Vec<&'a Value> {
+        self.segments.iter().fold(vec![value], |nodes, segment| {
+            let mut selected = vec![];
+            for node in nodes {
+                if segment.descendant {
+                    let mut descendants = vec![];
+                    visit(node, &mut descendants);
+                    for descendant in descendants {
+                        segment.key.select(descendant, &mut selected);
+                    }
+                } else {
+                    segment.key.select(node, &mut selected);
+                }
+            }
+            selected
+        })
+    }
+}
+
+impl Key {
+    fn select<'a>(&self, node: &'a Value, selected: &mut Vec<&'a Value>) {
+        match (self, node) {
+            (Key::Name(name), Value::Object(object)) => selected.extend(object.get(name)),
+            (Key::Index(index), Value::Array(array)) => {
+                let index = if *index < 0 {
+                    array.len() as i64 + index
+                } else {
+                    *index
+                };
+                selected.extend(usize::try_from(index).ok().and_then(|i| array.get(i)))
+            }
+            (Key::Wildcard, Value::Array(array)) => selected.extend(array),
+            (Key::Wildcard, Value::Object(object)) => selected.extend(object.values()),
+            _ => {}
+        }
+    }
+}
+
+/// visit collects the node and all its descendants in document order.
+fn visit<'a>(node: &'a Value, nodes: &mut Vec<&'a Value>) {
+    nodes.push(node);
+    match node {
+        Value::Array(array) => array.iter().for_each(|value| visit(value, nodes)),
+        Value::Object(object) => object.values().for_each(|value| visit(value, nodes)),
+        _ => {}
+    }
+}
+
+/// parse_segment parses a segment in front of `rest`, and returns the remaining path.
+fn parse_segment(rest: &str) -> Option<(Segment, &str)> {
+    let (descendant, rest) = match rest.strip_prefix("..") {
+        Some(rest) => (true, rest),
+        None => (false, rest),
+    };
+    let (key, rest) = if rest.starts_with('[') {
+        parse_bracket(rest)?
+    } else if descendant {
+        parse_name(rest)?
+    } else {
+        parse_name(rest.strip_prefix('.')?)?
+    };
+    Some((Segment { descendant, key }, rest))
+}
+
+/// parse_name parses the shorthand `*` or `name` following a dot.
+fn parse_name(rest: &str) -> Option<(Key, &str)> {
+    if let Some(rest) = rest.strip_prefix('*') {
+        return Some((Key::Wildcard, rest));
+    }
+    let end = rest.find(&['.', '['][..]).unwrap_or(rest.len());
+    if end == 0 {
+        return None;
+    }
+    Some((Key::Name(rest[..end].to_string()), &rest[end..]))
+}
+
+/// parse_bracket parses `[*]`, `[index]`, `['name']` or `["name"]`.
+fn parse_bracket(rest: &str) -> Option<(Key, &str)> {
+    let rest = rest.strip_prefix('[')?;
+    for quote in ['\'', '"'] {
+        if let Some(quoted) = rest.strip_prefix(quote) {
+            let end = quoted.find(quote)?;
+            let rest = quoted[end + 1..].strip_prefix(']')?;
+            return Some((Key::Name(quoted[..end].to_string()), rest));
+        }
+    }
+    let end = rest.find(']')?;
+    let key = match rest[..end].trim() {
+        "*" => Key::Wildcard,
+        index => Key::Index(index.parse().ok()?),
+    };
+    Some((key, &rest[end + 1..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::handler::http::json_path::JsonPath;
+
+    #[test]
+    fn test_json_path_query() {
+        let value = json!({
+            "payment": {"method": "card", "amount": 10},
+            "items": [{"id": 1, "tags": ["a"]}, {"id": 2, "tags": ["b", "c"]}],
+            "key.with.dots": true
+        });
+        let query = |path| JsonPath::parse(path).unwrap().query(&value);
+
+        assert_eq!(query("$"), vec![&value]);
+        assert_eq!(query("$.payment.method"), vec![&json!("card")]);
+        assert_eq!(query("$['payment'][\"amount\"]"), vec![&json!(10)]);
+        assert_eq!(query("$['key.with.dots']"), vec![&json!(true)]);
+        assert_eq!(query("$.items[1].id"), vec![&json!(2)]);
+        assert_eq!(query("$.items[-1].tags[0]"), vec![&json!("b")]);
+        assert_eq!(query("$.items[*].id"), vec![&json!(1), &json!(2)]);
+        assert_eq!(query("$.items.*.tags[0]"), vec![&json!("a"), &json!("b")]);
+        assert_eq!(query("$..id"), vec![&json!(1), &json!(2)]);
+        assert_eq!(
+            query("$..tags[*]"),
+            vec![&json!("a"), &json!("b"), &json!("c")]
+        );
+        assert_eq!(query("$..[1]"), vec![&value["items"][1], &json!("c")]);
+        assert!(query("$.payment.currency").is_empty());
+        assert!(query("$.items[2]").is_empty());
+        assert!(query("$.payment[0]").is_empty());
+
+        for invalid in [
+            "", "payment", "$payment", "$.", "$[0", "$['a]", "$[a]", "$..", "$.a.",
+        ] {
+            assert!(JsonPath::parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+}
@@ -1,3 +1,6 @@
 pub mod action;
+pub mod cookie;
+pub mod encoding;
+pub mod json_path;
 pub mod rule;
 pub mod selector;
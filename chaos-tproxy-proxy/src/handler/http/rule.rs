@@ -1,15 +1,127 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
 use crate::handler::http::action::Actions;
 use crate::handler::http::selector::Selector;
 
 /// Rule introduces a set of rules would effect the HTTP request/response.
 #[derive(Debug, Clone)]
 pub struct Rule {
+    /// name identifies the rule in logs.
+    pub name: Option<String>,
+    /// rules with higher priority would be applied first.
+    pub priority: i32,
+    /// rules after an applied terminal rule would be skipped.
+    pub terminal: bool,
     /// target would indicate which would be affected by the rule, HTTP request or response.
     pub target: Target,
     /// Selectors contains a set of filters to check whether the request/response should be affected.
     pub selector: Selector,
     /// actions introduces the expected modification.
     pub actions: Actions,
+    /// response_actions introduces the modification on the response of the matched request,
+    /// only for [Target::Request].
+    pub response_actions: Option<Actions>,
+    /// limit introduces which of the matched requests/responses would be affected.
+    pub limit: Option<HitLimit>,
+    /// the rule would be active after `start_after` since the proxy started.
+    pub start_after: Option<Duration>,
+    /// the rule would be inactive after `duration` since it's started.
+    pub duration: Option<Duration>,
+    /// the rule would be turned on and off periodically since it's started.
+    pub schedule: Option<Schedule>,
+}
+
+impl Rule {
+    /// active checks whether the rule is active at `elapsed` since the proxy started.
+    pub fn active(&self, elapsed: Duration) -> bool {
+        let elapsed = match elapsed.checked_sub(self.start_after.unwrap_or_default()) {
+            None => return false,
+            Some(elapsed) => elapsed,
+        };
+        self.duration.iter().all(|duration| elapsed < *duration)
+            && self.schedule.iter().all(|schedule| schedule.on(elapsed))
+    }
+
+    /// requires_request_body checks whether the request body is required to select the rule,
+    /// by the selector, or by the body tests of the actions on requests.
+    pub fn requires_request_body(&self) -> bool {
+        self.selector.requires_body()
+            || (self.target == Target::Request && self.actions.requires_body())
+    }
+
+    /// hit decides whether the actions would be applied on a matched request/response,
+    /// by the hit-count limit and the probability of actions.
+    pub fn hit(&self) -> bool {
+        match &self.limit {
+            None => self.actions.hit(),
+            Some(limit) => limit.hit(|| self.actions.hit()),
+        }
+    }
+}
+
+/// HitLimit limits the rule by counting the matched requests/responses. The counters are shared
+/// by all clones, so the limit is effective across all the connections.
+#[derive(Debug, Clone, Default)]
+pub struct HitLimit {
+    /// only the first `n` matched would be hit.
+    pub first: Option<u64>,
+    /// only every `n`-th matched would be hit.
+    pub every: Option<u64>,
+    /// the rule would not be hit any more after `n` hits.
+    pub max_hits: Option<u64>,
+    matched: Arc<AtomicU64>,
+    hits: Arc<AtomicU64>,
+}
+
+impl HitLimit {
+    pub fn new(first: Option<u64>, every: Option<u64>, max_hits: Option<u64>) -> Self {
+        Self {
+            first,
+            every,
+            max_hits,
+            ..Default::default()
+        }
+    }
+
+    /// hit counts a match, `probability` is checked only if the match is within the limit.
+    fn hit(&self, probability: impl FnOnce() -> bool) -> bool {
+        let n = self.matched.fetch_add(1, Ordering::SeqCst) + 1;
+        if matches!(self.first, Some(first) if n > first)
+            || matches!(self.every, Some(every) if n % every != 0)
+            || !probability()
+        {
+            return false;
+        }
+        match self.max_hits {
+            None => true,
+            Some(max_hits) => self
+                .hits
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |hits| {
+                    if hits < max_hits {
+                        Some(hits + 1)
+                    } else {
+                        None
+                    }
+                })
+                .is_ok(),
+        }
+    }
+}
+
+/// Schedule turns the rule on for `on`, and then off for `off`, repeatedly.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Schedule {
+    pub on: Duration,
+    pub off: Duration,
+}
+
+impl Schedule {
+    fn on(&self, elapsed: Duration) -> bool {
+        let period = (self.on + self.off).as_nanos();
+        elapsed.as_nanos() % period < self.on.as_nanos()
+    }
 }
 
 /// Target introduces the [Rule] should effect on HTTP request or response.
@@ -18,3 +130,76 @@ pub enum Target {
     Request,
     Response,
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::handler::http::action::Actions;
+    use crate::handler::http::rule::{HitLimit, Rule, Schedule, Target};
+    use crate::handler::http::selector::Selector;
+
+    fn hits(limit: &HitLimit, n: usize) -> Vec<bool> {
+        (0..n).map(|_| limit.hit(|| true)).collect()
+    }
+
+    #[test]
+    fn test_hit_limit() {
+        let limit = HitLimit::new(Some(2), None, None);
+        assert_eq!(hits(&limit, 4), vec![true, true, false, false]);
+
+        let limit = HitLimit::new(None, Some(3), None);
+        assert_eq!(
+            hits(&limit, 7),
+            vec![false, false, true, false, false, true, false]
+        );
+
+        let limit = HitLimit::new(None, Some(2), Some(2));
+        assert_eq!(
+            hits(&limit, 8),
+            vec![false, true, false, true, false, false, false, false]
+        );
+
+        // the counters are shared by clones
+        let limit = HitLimit::new(None, None, Some(1));
+        assert!(limit.clone().hit(|| true));
+        assert!(!limit.hit(|| true));
+
+        // missed by probability would not be counted as a hit
+        let limit = HitLimit::new(None, None, Some(1));
+        assert!(!limit.hit(|| false));
+        assert!(limit.hit(|| true));
+    }
+
+    #[test]
+    fn test_rule_active() {
+        let secs = Duration::from_secs;
+        let mut rule = Rule {
+            name: None,
+            priority: 0,
+            terminal: false,
+            target: Target::Request,
+            selector: Selector::default(),
+            actions: Actions::default(),
+            response_actions: None,
+            limit: None,
+            start_after: Some(secs(10)),
+            duration: Some(secs(300)),
+            schedule: None,
+        };
+        assert!(!rule.active(secs(9)));
+        assert!(rule.active(secs(10)));
+        assert!(rule.active(secs(309)));
+        assert!(!rule.active(secs(310)));
+
+        rule.schedule = Some(Schedule {
+            on: secs(30),
+            off: secs(90),
+        });
+        assert!(rule.active(secs(10)));
+        assert!(rule.active(secs(39)));
+        assert!(!rule.active(secs(40)));
+        assert!(!rule.active(secs(129)));
+        assert!(rule.active(secs(130)));
+    }
+}
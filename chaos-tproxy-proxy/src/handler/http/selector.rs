@@ -1,23 +1,225 @@
-use std::net::IpAddr;
+use std::net::{IpAddr, SocketAddr};
 
-use http::header::HeaderMap;
+use http::header::{HeaderMap, HeaderName};
+use http::uri::Authority;
 use http::{Method, Request, Response, StatusCode, Uri};
 use hyper::Body;
+use ipnet::IpNet;
+use regex::Regex;
+use serde_json::Value;
 use wildmatch::WildMatch;
 
+use crate::handler::http::cookie::request_cookies;
+use crate::handler::http::json_path::JsonPath;
 use crate::raw_config::Role;
 
-/// Selector could
-/// TODO(@STRRL): refactor to different filters, each with only required parameters.
-/// Or make these functions as methods of `Selector`.
-#[derive(Debug, Clone)]
+/// Selector selects the request (or the response) matched with all the given fields, each field
+/// is checked by its own method with only required parameters. Selectors could be composed
+/// with `all_of`, `any_of` and `not`.
+#[derive(Debug, Clone, Default)]
 pub struct Selector {
     pub port: Option<u16>,
+    /// the client address should be contained in any of the CIDRs.
+    pub source_cidrs: Option<Vec<IpNet>>,
+    /// the target address should be contained in any of the CIDRs.
+    pub destination_cidrs: Option<Vec<IpNet>>,
+    /// lowercase wildcard pattern of the host, matched with or without the port.
+    pub host: Option<WildMatch>,
     pub path: Option<WildMatch>,
+    pub path_regex: Option<Regex>,
     pub method: Option<Method>,
     pub code: Option<StatusCode>,
     pub request_headers: Option<HeaderMap>,
     pub response_headers: Option<HeaderMap>,
+    /// pairs of header name and the predicate on its values.
+    pub request_header_matches: Option<Vec<(HeaderName, HeaderPredicate)>>,
+    pub response_header_matches: Option<Vec<(HeaderName, HeaderPredicate)>>,
+    /// select the request by its (decoded) body, the body is buffered only if it's present.
+    pub request_body: Option<BodySelector>,
+    /// pairs of query key and the wildcard pattern of its value.
+    pub queries: Option<Vec<(String, WildMatch)>>,
+    /// pairs of request cookie name and the wildcard pattern of its value.
+    pub cookies: Option<Vec<(String, WildMatch)>>,
+
+    // the combinators of nested selectors.
+    /// all of the selectors should be matched.
+    pub all_of: Option<Vec<Selector>>,
+    /// at least one of the selectors should be matched.
+    pub any_of: Option<Vec<Selector>>,
+    /// the selector should not be matched.
+    pub not: Option<Box<Selector>>,
+}
+
+/// BodySelector selects the request by its body content.
+#[derive(Debug, Clone)]
+pub enum BodySelector {
+    /// any of the values selected by JSONPath `path` equals to `value`.
+    JSONPath { path: JsonPath, value: Value },
+    /// the body contains a match of the regex, a substring would be escaped into a regex.
+    Regex(regex::bytes::Regex),
+}
+
+impl BodySelector {
+    pub fn matches(&self, body: &[u8]) -> bool {
+        match self {
+            BodySelector::JSONPath { path, value } => {
+                let json: Value = match serde_json::from_slice(body) {
+                    Ok(json) => json,
+                    Err(_) => return false,
+                };
+                path.query(&json).contains(&value)
+            }
+            BodySelector::Regex(regex) => regex.is_match(body),
+        }
+    }
+}
+
+impl Selector {
+    /// requires_body checks whether the request body is required by this or nested selectors.
+    pub fn requires_body(&self) -> bool {
+        self.request_body.is_some() || self.nested().any(Selector::requires_body)
+    }
+
+    /// nested returns all the selectors nested in the combinators.
+    fn nested(&self) -> impl Iterator<Item = &Selector> {
+        self.all_of
+            .iter()
+            .chain(self.any_of.iter())
+            .flatten()
+            .chain(self.not.as_deref())
+    }
+
+    /// select_nested checks the combinators with `select`, which selects with a nested selector.
+    fn select_nested(&self, select: impl Fn(&Selector) -> bool) -> bool {
+        self.all_of.iter().flatten().all(&select)
+            && self
+                .any_of
+                .iter()
+                .all(|selectors| selectors.iter().any(&select))
+            && !self.not.as_deref().map_or(false, &select)
+    }
+
+    /// select_addrs checks the port and CIDRs with the client and the target address.
+    fn select_addrs(&self, remote: SocketAddr, target: SocketAddr) -> bool {
+        self.port.iter().all(|p| target.port() == *p)
+            && self
+                .source_cidrs
+                .iter()
+                .all(|cidrs| cidrs.iter().any(|cidr| cidr.contains(&remote.ip())))
+            && self
+                .destination_cidrs
+                .iter()
+                .all(|cidrs| cidrs.iter().any(|cidr| cidr.contains(&target.ip())))
+    }
+
+    /// select_body checks the body is matched with `request_body`,
+    /// `None` means the body is not buffered or could not be decoded.
+    fn select_body(&self, body: Option<&[u8]>) -> bool {
+        match &self.request_body {
+            None => true,
+            Some(selector) => body.map_or(false, |body| selector.matches(body)),
+        }
+    }
+
+    fn select_host(&self, authority: Option<&Authority>) -> bool {
+        let pattern = match &self.host {
+            None => return true,
+            Some(pattern) => pattern,
+        };
+        authority.map_or(false, |authority| {
+            pattern.matches(&authority.host().to_ascii_lowercase())
+                || pattern.matches(&authority.as_str().to_ascii_lowercase())
+        })
+    }
+
+    fn select_path(&self, path: &str) -> bool {
+        self.path.iter().all(|p| p.matches(path))
+            && self.path_regex.iter().all(|r| r.is_match(path))
+    }
+
+    /// select_queries checks every selected key has a value matched with its pattern.
+    fn select_queries(&self, uri: &Uri) -> bool {
+        let selected = match &self.queries {
+            None => return true,
+            Some(selected) => selected,
+        };
+        let queries: Vec<(String, String)> =
+            match serde_urlencoded::from_str(uri.query().unwrap_or("")) {
+                Ok(queries) => queries,
+                Err(_) => return false,
+            };
+        selected
+            .iter()
+            .all(|(key, pattern)| queries.iter().any(|(k, v)| k == key && pattern.matches(v)))
+    }
+
+    /// select_cookies checks every selected cookie has a value matched with its pattern.
+    fn select_cookies(&self, headers: &HeaderMap) -> bool {
+        let selected = match &self.cookies {
+            None => return true,
+            Some(selected) => selected,
+        };
+        let cookies = request_cookies(headers);
+        selected
+            .iter()
+            .all(|(name, pattern)| cookies.iter().any(|(n, v)| n == name && pattern.matches(v)))
+    }
+
+    fn select_method(&self, method: &Method) -> bool {
+        self.method.iter().all(|m| method == m)
+    }
+}
+
+/// contains_headers checks all the given fields are contained in the headers.
+fn contains_headers(headers: &HeaderMap, fields: &HeaderMap) -> bool {
+    fields
+        .iter()
+        .all(|(header, value)| headers.get_all(header).iter().any(|f| f == value))
+}
+
+/// select_headers checks the headers contain all fields of `selected`.
+fn select_headers(headers: &HeaderMap, selected: &Option<HeaderMap>) -> bool {
+    selected
+        .iter()
+        .all(|fields| contains_headers(headers, fields))
+}
+
+/// HeaderPredicate checks the values of a header.
+#[derive(Debug, Clone)]
+pub enum HeaderPredicate {
+    /// any value of the header is matched with the wildcard pattern.
+    Wildcard(WildMatch),
+    /// any value of the header contains a match of the regex.
+    Regex(regex::bytes::Regex),
+    /// the header is present.
+    Present,
+    /// the header is absent.
+    Absent,
+}
+
+impl HeaderPredicate {
+    pub fn matches(&self, headers: &HeaderMap, header: &HeaderName) -> bool {
+        let mut values = headers.get_all(header).iter();
+        match self {
+            HeaderPredicate::Wildcard(pattern) => {
+                values.any(|value| matches!(value.to_str(), Ok(value) if pattern.matches(value)))
+            }
+            HeaderPredicate::Regex(regex) => values.any(|value| regex.is_match(value.as_bytes())),
+            HeaderPredicate::Present => headers.contains_key(header),
+            HeaderPredicate::Absent => !headers.contains_key(header),
+        }
+    }
+}
+
+/// match_headers checks the headers are matched with all the predicates.
+fn match_headers(
+    headers: &HeaderMap,
+    predicates: &Option<Vec<(HeaderName, HeaderPredicate)>>,
+) -> bool {
+    predicates
+        .iter()
+        .flatten()
+        .all(|(header, predicate)| predicate.matches(headers, header))
 }
 
 /// select_role checks the given src_ip (or dst_ip) is contained in the give role.
@@ -37,56 +239,106 @@ pub fn select_role(src_ip: &IpAddr, dst_ip: &IpAddr, role: &Role) -> bool {
     }
 }
 
+/// may_select_request checks the request fields except the body, and ignores nested selectors.
+/// It's required for both [select_request] and [select_response] to be matched, so the body is
+/// buffered only if the rule may be matched.
+pub fn may_select_request(
+    remote: SocketAddr,
+    target: SocketAddr,
+    authority: Option<&Authority>,
+    request: &Request<Body>,
+    selector: &Selector,
+) -> bool {
+    selector.select_addrs(remote, target)
+        && selector.select_host(authority)
+        && selector.select_path(request.uri().path())
+        && selector.select_queries(request.uri())
+        && selector.select_method(request.method())
+        && select_headers(request.headers(), &selector.request_headers)
+        && match_headers(request.headers(), &selector.request_header_matches)
+        && selector.select_cookies(request.headers())
+}
+
 /// select_request would check the given request is matched with the given selector.
-pub fn select_request(port: u16, request: &Request<Body>, selector: &Selector) -> bool {
-    selector.port.iter().all(|p| port == *p)
-        && selector
-            .path
-            .iter()
-            .all(|p| p.matches(request.uri().path()))
-        && selector.method.iter().all(|m| request.method() == m)
-        && selector.request_headers.iter().all(|fields| {
-            fields
-                .iter()
-                .all(|(header, value)| request.headers().get_all(header).iter().any(|f| f == value))
+/// `remote` is the client address and `target` is the address the request is sent to.
+/// `authority` is the authority the request is sent to, see `HttpService::authority`.
+/// `body` is the decoded request body, only buffered if any selector requires it.
+pub fn select_request(
+    remote: SocketAddr,
+    target: SocketAddr,
+    authority: Option<&Authority>,
+    request: &Request<Body>,
+    body: Option<&[u8]>,
+    selector: &Selector,
+) -> bool {
+    may_select_request(remote, target, authority, request, selector)
+        && selector.select_body(body)
+        && selector.select_nested(|nested| {
+            select_request(remote, target, authority, request, body, nested)
         })
 }
 
 /// select_response would check the given request and response is matched with the given selector.
+#[allow(clippy::too_many_arguments)]
 pub fn select_response(
-    port: u16,
+    remote: SocketAddr,
+    target: SocketAddr,
+    authority: Option<&Authority>,
     uri: &Uri,
     method: &Method,
     request_headers: &HeaderMap,
+    request_body: Option<&[u8]>,
     response: &Response<Body>,
     selector: &Selector,
 ) -> bool {
-    selector.port.iter().all(|p| port == *p)
-        && selector.path.iter().all(|p| p.matches(uri.path()))
-        && selector.method.iter().all(|m| method == m)
+    selector.select_addrs(remote, target)
+        && selector.select_host(authority)
+        && selector.select_body(request_body)
+        && selector.select_path(uri.path())
+        && selector.select_queries(uri)
+        && selector.select_method(method)
         && selector.code.iter().all(|code| response.status() == *code)
-        && selector.request_headers.iter().all(|fields| {
-            fields
-                .iter()
-                .all(|(header, value)| request_headers.get_all(header).iter().any(|f| f == value))
-        })
-        && selector.response_headers.iter().all(|fields| {
-            fields.iter().all(|(header, value)| {
-                response
-                    .headers()
-                    .get_all(header)
-                    .iter()
-                    .any(|f| f == value)
-            })
+        && select_headers(request_headers, &selector.request_headers)
+        && select_headers(response.headers(), &selector.response_headers)
+        && match_headers(request_headers, &selector.request_header_matches)
+        && selector.select_cookies(request_headers)
+        && match_headers(response.headers(), &selector.response_header_matches)
+        && selector.select_nested(|nested| {
+            select_response(
+                remote,
+                target,
+                authority,
+                uri,
+                method,
+                request_headers,
+                request_body,
+                response,
+                nested,
+            )
         })
 }
 
 #[cfg(test)]
 mod tests {
-    use http::Request;
+    use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+
+    use http::header::{HeaderName, AUTHORIZATION, USER_AGENT};
+    use http::uri::Authority;
+    use http::{HeaderMap, Method, Request};
     use hyper::Body;
+    use regex::Regex;
+    use serde_json::json;
+    use wildmatch::WildMatch;
+
+    use crate::handler::http::json_path::JsonPath;
+    use crate::handler::http::selector::{select_request, BodySelector, HeaderPredicate, Selector};
+
+    const REMOTE: SocketAddr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(10, 0, 1, 2), 40000));
+    const TARGET: SocketAddr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(10, 0, 2, 3), 80));
 
-    use crate::handler::http::selector::{select_request, Selector};
+    fn addr(port: u16) -> SocketAddr {
+        SocketAddr::new(TARGET.ip(), port)
+    }
 
     #[test]
     fn test_select_request() {
@@ -98,9 +350,13 @@ mod tests {
             code: None,
             request_headers: None,
             response_headers: None,
+            ..Default::default()
         };
         let req = Request::builder().body(Body::empty()).unwrap();
-        assert_eq!(select_request(port, &req, &selector), true);
+        assert_eq!(
+            select_request(REMOTE, addr(port), None, &req, None, &selector),
+            true
+        );
 
         let mut selector = Selector {
             port: None,
@@ -109,23 +365,441 @@ mod tests {
             code: None,
             request_headers: None,
             response_headers: None,
+            ..Default::default()
         };
         let req = Request::builder()
             .uri("http://www.google.com/src/")
             .body(Body::empty())
             .unwrap();
-        assert_eq!(select_request(0, &req, &selector), false);
+        assert_eq!(
+            select_request(REMOTE, TARGET, None, &req, None, &selector),
+            false
+        );
 
         selector.path = Some(wildmatch::WildMatch::new("src"));
-        assert_eq!(select_request(0, &req, &selector), false);
+        assert_eq!(
+            select_request(REMOTE, TARGET, None, &req, None, &selector),
+            false
+        );
 
         selector.path = Some(wildmatch::WildMatch::new("/src/"));
-        assert_eq!(select_request(0, &req, &selector), true);
+        assert_eq!(
+            select_request(REMOTE, TARGET, None, &req, None, &selector),
+            true
+        );
 
         selector.path = Some(wildmatch::WildMatch::new("/src*"));
-        assert_eq!(select_request(0, &req, &selector), true);
+        assert_eq!(
+            select_request(REMOTE, TARGET, None, &req, None, &selector),
+            true
+        );
 
         selector.path = Some(wildmatch::WildMatch::new("/src?"));
-        assert_eq!(select_request(0, &req, &selector), true);
+        assert_eq!(
+            select_request(REMOTE, TARGET, None, &req, None, &selector),
+            true
+        );
+    }
+
+    #[test]
+    fn test_select_request_negated() {
+        let mut selector = Selector {
+            path_regex: Some(Regex::new("^/(api|v1)/").unwrap()),
+            not: Some(Box::new(Selector {
+                any_of: Some(vec![
+                    Selector {
+                        path: Some(WildMatch::new("*/healthz")),
+                        ..Default::default()
+                    },
+                    Selector {
+                        method: Some(Method::HEAD),
+                        ..Default::default()
+                    },
+                ]),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        let req = |method, uri| {
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .header("x-probe", "kubelet")
+                .body(Body::empty())
+                .unwrap()
+        };
+        assert!(select_request(
+            REMOTE,
+            TARGET,
+            None,
+            &req(Method::GET, "/api/orders"),
+            None,
+            &selector
+        ));
+        assert!(select_request(
+            REMOTE,
+            TARGET,
+            None,
+            &req(Method::GET, "/v1/orders"),
+            None,
+            &selector
+        ));
+        assert!(!select_request(
+            REMOTE,
+            TARGET,
+            None,
+            &req(Method::GET, "/v2/orders"),
+            None,
+            &selector
+        ));
+        assert!(!select_request(
+            REMOTE,
+            TARGET,
+            None,
+            &req(Method::GET, "/api/healthz"),
+            None,
+            &selector
+        ));
+        assert!(!select_request(
+            REMOTE,
+            TARGET,
+            None,
+            &req(Method::HEAD, "/api/orders"),
+            None,
+            &selector
+        ));
+
+        let mut excluded = HeaderMap::new();
+        excluded.insert("x-probe", "kubelet".parse().unwrap());
+        let not = selector.not.as_mut().unwrap();
+        not.any_of.as_mut().unwrap().push(Selector {
+            request_headers: Some(excluded),
+            ..Default::default()
+        });
+        assert!(!select_request(
+            REMOTE,
+            TARGET,
+            None,
+            &req(Method::GET, "/api/orders"),
+            None,
+            &selector
+        ));
+    }
+
+    #[test]
+    fn test_select_request_queries() {
+        let selector = Selector {
+            queries: Some(vec![
+                ("version".to_string(), WildMatch::new("v2*")),
+                ("tenant".to_string(), WildMatch::new("*")),
+            ]),
+            ..Default::default()
+        };
+        let req = |uri| Request::builder().uri(uri).body(Body::empty()).unwrap();
+        assert!(select_request(
+            REMOTE,
+            TARGET,
+            None,
+            &req("/a?tenant=x&version=v2.1"),
+            None,
+            &selector
+        ));
+        assert!(select_request(
+            REMOTE,
+            TARGET,
+            None,
+            &req("/a?version=v1&version=v2&tenant=y"),
+            None,
+            &selector
+        ));
+        assert!(!select_request(
+            REMOTE,
+            TARGET,
+            None,
+            &req("/a?version=v1&tenant=x"),
+            None,
+            &selector
+        ));
+        assert!(!select_request(
+            REMOTE,
+            TARGET,
+            None,
+            &req("/a?version=v2"),
+            None,
+            &selector
+        ));
+        assert!(!select_request(
+            REMOTE,
+            TARGET,
+            None,
+            &req("/a"),
+            None,
+            &selector
+        ));
+    }
+
+    #[test]
+    fn test_select_request_host() {
+        let selector = Selector {
+            host: Some(WildMatch::new("*.example.com")),
+            ..Default::default()
+        };
+        let req = Request::builder().body(Body::empty()).unwrap();
+        let authority = Authority::from_static;
+        assert!(select_request(
+            REMOTE,
+            TARGET,
+            Some(&authority("api.example.com")),
+            &req,
+            None,
+            &selector
+        ));
+        assert!(select_request(
+            REMOTE,
+            TARGET,
+            Some(&authority("API.example.com:8080")),
+            &req,
+            None,
+            &selector
+        ));
+        assert!(!select_request(
+            REMOTE,
+            TARGET,
+            Some(&authority("example.org")),
+            &req,
+            None,
+            &selector
+        ));
+        assert!(!select_request(REMOTE, TARGET, None, &req, None, &selector));
+    }
+
+    #[test]
+    fn test_select_request_body() {
+        let mut selector = Selector {
+            request_body: Some(BodySelector::JSONPath {
+                path: JsonPath::parse("$.payment.method").unwrap(),
+                value: json!("card"),
+            }),
+            ..Default::default()
+        };
+        let req = Request::builder().body(Body::empty()).unwrap();
+        let card = br#"{"payment":{"method":"card","amount":10}}"#;
+        let cash = br#"{"payment":{"method":"cash","amount":10}}"#;
+        assert!(select_request(
+            REMOTE,
+            TARGET,
+            None,
+            &req,
+            Some(card),
+            &selector
+        ));
+        assert!(!select_request(
+            REMOTE,
+            TARGET,
+            None,
+            &req,
+            Some(cash),
+            &selector
+        ));
+        assert!(!select_request(
+            REMOTE,
+            TARGET,
+            None,
+            &req,
+            Some(b"not json"),
+            &selector
+        ));
+        assert!(!select_request(REMOTE, TARGET, None, &req, None, &selector));
+
+        selector.request_body = Some(BodySelector::Regex(
+            regex::bytes::Regex::new(r#""amount":\d{2,}"#).unwrap(),
+        ));
+        assert!(select_request(
+            REMOTE,
+            TARGET,
+            None,
+            &req,
+            Some(cash),
+            &selector
+        ));
+        assert!(!select_request(
+            REMOTE,
+            TARGET,
+            None,
+            &req,
+            Some(br#"{"amount":9}"#),
+            &selector
+        ));
+    }
+
+    #[test]
+    fn test_select_request_cidrs() {
+        let mut selector = Selector {
+            source_cidrs: Some(vec![
+                "10.0.1.0/24".parse().unwrap(),
+                "10.0.9.0/24".parse().unwrap(),
+            ]),
+            ..Default::default()
+        };
+        let req = Request::builder().body(Body::empty()).unwrap();
+        assert!(select_request(REMOTE, TARGET, None, &req, None, &selector));
+
+        let remote = SocketAddr::new(Ipv4Addr::new(10, 0, 3, 2).into(), 40000);
+        assert!(!select_request(remote, TARGET, None, &req, None, &selector));
+
+        selector.destination_cidrs = Some(vec!["10.0.2.3/32".parse().unwrap()]);
+        assert!(select_request(REMOTE, TARGET, None, &req, None, &selector));
+        assert!(!select_request(REMOTE, REMOTE, None, &req, None, &selector));
+    }
+
+    #[test]
+    fn test_select_request_combinators() {
+        let path = |p| Selector {
+            path: Some(WildMatch::new(p)),
+            ..Default::default()
+        };
+        let mut selector = Selector {
+            any_of: Some(vec![path("/a*"), path("/b*")]),
+            not: Some(Box::new(Selector {
+                any_of: Some(vec![path("*/healthz"), path("*/readyz")]),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        let req = |uri| Request::builder().uri(uri).body(Body::empty()).unwrap();
+        assert!(select_request(
+            REMOTE,
+            TARGET,
+            None,
+            &req("/a/1"),
+            None,
+            &selector
+        ));
+        assert!(select_request(
+            REMOTE,
+            TARGET,
+            None,
+            &req("/b/1"),
+            None,
+            &selector
+        ));
+        assert!(!select_request(
+            REMOTE,
+            TARGET,
+            None,
+            &req("/c/1"),
+            None,
+            &selector
+        ));
+        assert!(!select_request(
+            REMOTE,
+            TARGET,
+            None,
+            &req("/a/healthz"),
+            None,
+            &selector
+        ));
+        assert!(!selector.requires_body());
+
+        selector.all_of = Some(vec![
+            path("*/1"),
+            Selector {
+                request_body: Some(BodySelector::Regex(regex::bytes::Regex::new("x").unwrap())),
+                ..Default::default()
+            },
+        ]);
+        assert!(selector.requires_body());
+        assert!(select_request(
+            REMOTE,
+            TARGET,
+            None,
+            &req("/a/1"),
+            Some(b"x"),
+            &selector
+        ));
+        assert!(!select_request(
+            REMOTE,
+            TARGET,
+            None,
+            &req("/a/1"),
+            Some(b"y"),
+            &selector
+        ));
+        assert!(!select_request(
+            REMOTE,
+            TARGET,
+            None,
+            &req("/a/2"),
+            Some(b"x"),
+            &selector
+        ));
+    }
+
+    #[test]
+    fn test_select_request_header_matches() {
+        let selector = Selector {
+            request_header_matches: Some(vec![
+                (
+                    HeaderName::from_static("x-canary"),
+                    HeaderPredicate::Wildcard(WildMatch::new("v2-*")),
+                ),
+                (AUTHORIZATION, HeaderPredicate::Absent),
+            ]),
+            ..Default::default()
+        };
+        let req = |headers: &[(&str, &str)]| {
+            let mut builder = Request::builder();
+            for (name, value) in headers {
+                builder = builder.header(*name, *value);
+            }
+            builder.body(Body::empty()).unwrap()
+        };
+        let select = |req| select_request(REMOTE, TARGET, None, &req, None, &selector);
+        assert!(select(req(&[("x-canary", "v1"), ("x-canary", "v2-beta")])));
+        assert!(!select(req(&[("x-canary", "v1")])));
+        assert!(!select(req(&[])));
+        assert!(!select(req(&[
+            ("x-canary", "v2-beta"),
+            ("authorization", "Bearer t")
+        ])));
+
+        let selector = Selector {
+            request_header_matches: Some(vec![
+                (
+                    USER_AGENT,
+                    HeaderPredicate::Regex(regex::bytes::Regex::new("^curl/7\\.").unwrap()),
+                ),
+                (AUTHORIZATION, HeaderPredicate::Present),
+            ]),
+            ..Default::default()
+        };
+        let select = |req| select_request(REMOTE, TARGET, None, &req, None, &selector);
+        assert!(select(req(&[
+            ("user-agent", "curl/7.64"),
+            ("authorization", "")
+        ])));
+        assert!(!select(req(&[
+            ("user-agent", "curl/8.0"),
+            ("authorization", "")
+        ])));
+        assert!(!select(req(&[("user-agent", "curl/7.64")])));
+    }
+
+    #[test]
+    fn test_select_request_cookies() {
+        let selector = Selector {
+            cookies: Some(vec![("session".to_string(), WildMatch::new("admin-*"))]),
+            ..Default::default()
+        };
+        let req = |cookie| {
+            Request::builder()
+                .header("cookie", cookie)
+                .body(Body::empty())
+                .unwrap()
+        };
+        let select = |req| select_request(REMOTE, TARGET, None, &req, None, &selector);
+        assert!(select(req("theme=dark; session=admin-42")));
+        assert!(!select(req("theme=dark; session=user-42")));
+        assert!(!select(req("theme=dark")));
     }
 }
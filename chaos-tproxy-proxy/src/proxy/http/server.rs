@@ -5,11 +5,12 @@ use std::net::SocketAddr;
 use std::pin::Pin;
 use std::sync::Arc;
 use std::task::{Context, Poll};
+use std::time::Instant;
 
 use anyhow::{anyhow, Result};
 use derivative::Derivative;
-use http::header::HOST;
-use http::uri::{PathAndQuery, Scheme, Uri};
+use http::header::{HeaderMap, CONTENT_LENGTH, HOST};
+use http::uri::{Authority, PathAndQuery, Scheme, Uri};
 use http::StatusCode;
 use hyper::server::conn::Http;
 use hyper::service::Service;
@@ -22,14 +23,25 @@ use tokio::sync::oneshot::Receiver;
 use tokio_rustls::TlsAcceptor;
 use tracing::{debug, error, span, trace, Level};
 
-use crate::handler::http::action::{apply_request_action, apply_response_action};
-use crate::handler::http::rule::Target;
-use crate::handler::http::selector::{select_request, select_response, select_role};
+use crate::handler::http::action::{
+    apply_request_action, apply_response_action, read_body_within, truncate_body, Actions,
+    CloseAction,
+};
+use crate::handler::http::encoding::decode_body_within;
+use crate::handler::http::rule::{Rule, Target};
+use crate::handler::http::selector::{
+    may_select_request, select_request, select_response, select_role,
+};
 use crate::proxy::http::config::{Config, HTTPConfig};
 use crate::proxy::http::connector::HttpConnector;
+use crate::proxy::tcp::connection::ConnectionHandle;
 use crate::proxy::tcp::listener::TcpListener;
 use crate::proxy::tcp::transparent_socket::TransparentSocket;
 
+/// MAX_BUFFERED_BODY is the max length of the body buffered for body selectors and body tests,
+/// neither of them is matched with a larger body.
+const MAX_BUFFERED_BODY: usize = 1 << 20;
+
 /// HttpServer is the proxy service behind the iptables tproxy. It would accept the forwarded
 /// connection from the iptables tproxy, and then let [HttpService] to handle the connection.
 pub struct HttpServer {
@@ -46,6 +58,8 @@ impl HttpServer {
         let listener = TcpListener::bind(addr)?;
         tracing::info!("Proxy Listening");
         let http_config = Arc::new(self.config.http_config.clone());
+        // rules are scheduled with the time elapsed since the proxy started
+        let started = Instant::now();
         let rx_mut = &mut rx;
 
         loop {
@@ -60,12 +74,22 @@ impl HttpServer {
             let addr_remote = stream.peer_addr()?;
             let addr_local = stream.local_addr()?;
             debug!(target : "Accept streaming", "remote={:?}, local={:?}",addr_remote, addr_local);
+            // only the connection is dropped if its socket couldn't be duplicated
+            let connection = match ConnectionHandle::new(&stream) {
+                Ok(connection) => connection,
+                Err(e) => {
+                    error!("fail to handle connection from {}: {}", addr_remote, e);
+                    continue;
+                }
+            };
             if let Some(tls_config) = &self.config.tls_config {
                 let tls_client_config = Arc::new(tls_config.tls_client_config.clone());
                 let tls_server_config = Arc::new(tls_config.tls_server_config.clone());
                 let service = HttpService::new(
                     addr_remote,
                     addr_local,
+                    started,
+                    connection,
                     http_config.clone(),
                     Some(tls_client_config.clone()),
                 );
@@ -79,7 +103,14 @@ impl HttpServer {
                     };
                 });
             } else {
-                let service = HttpService::new(addr_remote, addr_local, http_config.clone(), None);
+                let service = HttpService::new(
+                    addr_remote,
+                    addr_local,
+                    started,
+                    connection,
+                    http_config.clone(),
+                    None,
+                );
                 tokio::spawn(async move {
                     match serve_http_with_error_return(stream, &service).await {
                         Ok(_) => {}
@@ -192,6 +223,10 @@ pub async fn serve_http_with_error_return(
 pub struct HttpService {
     remote: SocketAddr,
     target: SocketAddr,
+    /// started is the time the proxy started serving, used to schedule rules.
+    started: Instant,
+    /// connection is the client side connection, used to apply [CloseAction].
+    connection: ConnectionHandle,
     config: Arc<HTTPConfig>,
 
     #[derivative(Debug = "ignore")]
@@ -202,12 +237,16 @@ impl HttpService {
     fn new(
         addr_remote: SocketAddr,
         addr_target: SocketAddr,
+        started: Instant,
+        connection: ConnectionHandle,
         config: Arc<HTTPConfig>,
         tls_client_config: Option<Arc<ClientConfig>>,
     ) -> Self {
         Self {
             remote: addr_remote,
             target: addr_target,
+            started,
+            connection,
             config,
             tls_client_config,
         }
@@ -224,47 +263,68 @@ impl HttpService {
         select_role(&self.remote.ip(), &self.target.ip(), &role)
     }
 
-    /// handle would execute the core inject and forward logic.
-    async fn handle(self, mut request: Request<Body>) -> Result<Response<Body>> {
-        let log_key = format!("{{remote = {}, target = {} }}", self.remote, self.target);
-        debug!("{} : Proxy is handling http request", log_key);
+    /// authority is the authority the request is sent to. It's taken from the request URI in the
+    /// absolute form, otherwise from the `Host` header, or the target address.
+    fn authority(&self, request: &Request<Body>) -> Result<Option<Authority>> {
+        if let Some(authority) = request.uri().authority() {
+            return Ok(Some(authority.clone()));
+        }
+        Ok(match request.headers().get(HOST) {
+            None => self.target.to_string().parse().ok(),
+            Some(value) => Some(value.as_bytes().try_into()?),
+        })
+    }
 
-        let role_ok = self.role_ok();
-        let request_rules: Vec<_> = self
-            .config
-            .rules
-            .iter()
-            .filter(|rule| {
-                role_ok
-                    && matches!(rule.target, Target::Request)
-                    && select_request(self.target.port(), &request, &rule.selector)
-            })
-            .collect();
+    /// requires_body checks whether the request body is required by any active rule of the target,
+    /// whose selector is matched with the other fields of the request. Request rules are matched
+    /// with the request before any action is applied, and response rules with the request after.
+    fn requires_body(
+        &self,
+        target: Target,
+        authority: Option<&Authority>,
+        request: &Request<Body>,
+    ) -> bool {
+        self.config.rules.iter().any(|rule| {
+            rule.target == target
+                && rule.requires_request_body()
+                && rule.active(self.started.elapsed())
+                && may_select_request(self.remote, self.target, authority, request, &rule.selector)
+        })
+    }
 
-        // inject chaos into request
-        for rule in request_rules {
-            debug!("{} : request matched, rule({:?})", log_key, rule);
-            request = apply_request_action(request, &rule.actions).await?;
+    /// tear_down applies the close action on the client connection, the error returned
+    /// makes hyper drop the connection without sending the response.
+    fn tear_down(&self, close: &CloseAction) -> Result<Response<Body>> {
+        match close {
+            CloseAction::Reset => self.connection.reset()?,
+            _ => self.connection.close()?,
         }
+        Err(anyhow!("{:?} applied", close))
+    }
 
-        let uri = request.uri().clone();
-        let method = request.method().clone();
-        let headers = request.headers().clone();
-        trace!("URI: {}", request.uri());
+    /// apply_response applies the actions on the response. The connection would be torn down by
+    /// the close action, except truncating which is merged into `truncate`.
+    async fn apply_response(
+        &self,
+        response: Response<Body>,
+        actions: &Actions,
+        truncate: &mut Option<usize>,
+    ) -> Result<Response<Body>> {
+        let response = apply_response_action(response, actions).await?;
+        match &actions.close {
+            Some(CloseAction::Truncate(n)) => *truncate = Some(truncate.map_or(*n, |t| t.min(*n))),
+            Some(close) => return self.tear_down(close),
+            None => {}
+        }
+        Ok(response)
+    }
+
+    /// forward would forward the request to the target, as the original request URL is rebuilt.
+    async fn forward(&self, mut request: Request<Body>, log_key: &str) -> Result<Response<Body>> {
         let mut parts = request.uri().clone().into_parts();
 
         // because the original request URL is not carried in the HTTP request, we should rebuild it.
-        parts.authority = match request
-            .headers()
-            .iter()
-            .find(|(header_name, _)| **header_name == HOST)
-        {
-            None => match self.target.to_string().parse() {
-                Ok(o) => Some(o),
-                Err(_) => None,
-            },
-            Some((_, value)) => Some(value.as_bytes().try_into()?),
-        };
+        parts.authority = self.authority(&request)?;
         trace!("authority: {:?}", parts.authority);
         if parts.path_and_query.is_none() {
             parts.path_and_query = Some(PathAndQuery::from_static("/"))
@@ -293,7 +353,7 @@ impl HttpService {
             client.request(request)
         };
 
-        let mut response = match rsp_fut.await {
+        Ok(match rsp_fut.await {
             Ok(resp) => resp,
             Err(err) => {
                 error!("{} : fail to forward request: {}", log_key, err);
@@ -301,35 +361,247 @@ impl HttpService {
                     .status(StatusCode::BAD_GATEWAY)
                     .body(Body::empty())?
             }
+        })
+    }
+
+    /// handle would execute the core inject and forward logic.
+    async fn handle(self, mut request: Request<Body>) -> Result<Response<Body>> {
+        let log_key = format!("{{remote = {}, target = {} }}", self.remote, self.target);
+        debug!("{} : Proxy is handling http request", log_key);
+
+        let role_ok = self.role_ok();
+        let authority = self.authority(&request)?;
+        let body = if role_ok && self.requires_body(Target::Request, authority.as_ref(), &request) {
+            let headers = request.headers().clone();
+            buffer_body(&headers, request.body_mut(), MAX_BUFFERED_BODY).await?
+        } else {
+            None
         };
+        let request_rules: Vec<_> = self
+            .config
+            .rules
+            .iter()
+            .enumerate()
+            .filter(|(_, rule)| {
+                role_ok
+                    && matches!(rule.target, Target::Request)
+                    && rule.active(self.started.elapsed())
+                    && select_request(
+                        self.remote,
+                        self.target,
+                        authority.as_ref(),
+                        &request,
+                        body.as_deref(),
+                        &rule.selector,
+                    )
+                    && rule.actions.select_body(body.as_deref())
+            })
+            .collect();
 
-        let response_rules: Vec<_> = self
+        // the response body would be truncated to the given length
+        let mut truncate: Option<usize> = None;
+        // the index of the applied terminal rule, rules after it would be skipped
+        let mut terminated: Option<usize> = None;
+        // the applied request rules with actions on the response
+        let mut correlated = vec![];
+        // the response mocked by `respond`, `status_codes` or `redirect`,
+        // the request would not be forwarded
+        let mut mocked = None;
+
+        // the redirect location is rendered with the URI before any action is applied
+        let original_uri = request.uri().clone();
+
+        // inject chaos into request
+        for (index, rule) in hit_rules(request_rules, &log_key, "request") {
+            debug!("{} : request matched, rule({:?})", log_key, rule.name);
+            if rule.terminal {
+                terminated = Some(index);
+            }
+            request = apply_request_action(request, &rule.actions).await?;
+            match &rule.actions.close {
+                Some(CloseAction::Truncate(n)) => {
+                    truncate = Some(truncate.map_or(*n, |t| t.min(*n)))
+                }
+                Some(close) => return self.tear_down(close),
+                None => {}
+            }
+            if let Some(actions) = &rule.response_actions {
+                correlated.push((rule, actions));
+            }
+            let response = match &rule.actions.redirect {
+                Some(redirect) => Some(redirect.response(&original_uri)?),
+                None => rule.actions.mocked_response(),
+            };
+            if let Some(response) = response {
+                mocked = Some(response);
+                break;
+            }
+        }
+
+        trace!("URI: {}", request.uri());
+        let authority = self.authority(&request)?;
+        let uri = request.uri().clone();
+        let method = request.method().clone();
+        let headers = request.headers().clone();
+        // the request body is buffered again, as it may be replaced or patched by the actions
+        let body = if role_ok && self.requires_body(Target::Response, authority.as_ref(), &request)
+        {
+            buffer_body(&headers, request.body_mut(), MAX_BUFFERED_BODY).await?
+        } else {
+            None
+        };
+
+        let mut response = match mocked {
+            Some(response) => {
+                debug!("{} : respond without forwarding", log_key);
+                response
+            }
+            None => self.forward(request, &log_key).await?,
+        };
+
+        let mut response_rules: Vec<_> = self
             .config
             .rules
             .iter()
-            .filter(|rule| {
+            .enumerate()
+            .filter(|(index, rule)| {
                 role_ok
+                    && terminated.map_or(true, |terminated| *index < terminated)
                     && matches!(rule.target, Target::Response)
+                    && rule.active(self.started.elapsed())
                     && select_response(
-                        self.target.port(),
+                        self.remote,
+                        self.target,
+                        authority.as_ref(),
                         &uri,
                         &method,
                         &headers,
+                        body.as_deref(),
                         &response,
                         &rule.selector,
                     )
             })
             .collect();
 
+        // the response body is buffered only for the body tests of actions
+        let response_body = if response_rules
+            .iter()
+            .any(|(_, rule)| rule.actions.requires_body())
+            || correlated
+                .iter()
+                .any(|(_, actions)| actions.requires_body())
+        {
+            let headers = response.headers().clone();
+            buffer_body(&headers, response.body_mut(), MAX_BUFFERED_BODY).await?
+        } else {
+            None
+        };
+        response_rules.retain(|(_, rule)| rule.actions.select_body(response_body.as_deref()));
+
+        // inject chaos into the response of matched request rules
+        for (rule, actions) in correlated {
+            if !actions.select_body(response_body.as_deref()) {
+                debug!(
+                    "{} : request matched but response missed by body test, rule({:?})",
+                    log_key, rule.name
+                );
+                continue;
+            }
+            if !actions.hit() {
+                debug!(
+                    "{} : request matched but response missed by percent, rule({:?})",
+                    log_key, rule.name
+                );
+                continue;
+            }
+            debug!(
+                "{} : request matched, apply response actions, rule({:?})",
+                log_key, rule.name
+            );
+            response = self
+                .apply_response(response, actions, &mut truncate)
+                .await?;
+        }
+
         // inject chaos into response
-        for rule in response_rules {
-            debug!("{} : response matched", log_key);
-            response = apply_response_action(response, &rule.actions).await?;
+        for (_, rule) in hit_rules(response_rules, &log_key, "response") {
+            debug!("{} : response matched, rule({:?})", log_key, rule.name);
+            response = self
+                .apply_response(response, &rule.actions, &mut truncate)
+                .await?;
+        }
+
+        if let Some(n) = truncate {
+            let body = std::mem::take(response.body_mut());
+            *response.body_mut() = truncate_body(body, n);
         }
         Ok(response)
     }
 }
 
+/// hit_rules checks the hits of the matched rules by their limits and percents in order. The hits
+/// are checked lazily, as the actions of a hit rule may end the handling, and the rules after a hit
+/// terminal rule are skipped.
+fn hit_rules<'a>(
+    rules: Vec<(usize, &'a Rule)>,
+    log_key: &'a str,
+    target: &'a str,
+) -> impl Iterator<Item = (usize, &'a Rule)> {
+    let mut rules = rules.into_iter();
+    let mut terminated = false;
+    std::iter::from_fn(move || {
+        if terminated {
+            return None;
+        }
+        let (index, rule) = rules.find(|(_, rule)| {
+            let hit = rule.hit();
+            if !hit {
+                debug!(
+                    "{} : {} matched but missed by limit or percent, rule({:?})",
+                    log_key, target, rule.name
+                );
+            }
+            hit
+        })?;
+        terminated = rule.terminal;
+        Some((index, rule))
+    })
+}
+
+/// buffer_body collects the body up to `limit` bytes and returns it decoded as `Content-Encoding`
+/// of the headers, the body is restored to be sent. `None` is returned if the body is larger than
+/// `limit` or could not be decoded.
+async fn buffer_body(
+    headers: &HeaderMap,
+    body: &mut Body,
+    limit: usize,
+) -> Result<Option<Vec<u8>>> {
+    let length = headers
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok()?.parse::<usize>().ok());
+    if length.map_or(false, |length| length > limit) {
+        debug!("body is too large to buffer: {:?} bytes", length);
+        return Ok(None);
+    }
+
+    let raw = match read_body_within(std::mem::take(body), limit).await? {
+        Ok(raw) => raw,
+        Err(restored) => {
+            debug!("body is too large to buffer: over {} bytes", limit);
+            *body = restored;
+            return Ok(None);
+        }
+    };
+    *body = Body::from(raw.clone());
+    match decode_body_within(headers, raw, limit) {
+        Ok(data) => Ok(Some(data)),
+        Err(e) => {
+            debug!("fail to decode body: {}", e);
+            Ok(None)
+        }
+    }
+}
+
 impl Service<Request<Body>> for HttpService {
     type Response = Response<Body>;
     type Error = anyhow::Error;
@@ -346,3 +618,288 @@ impl Service<Request<Body>> for HttpService {
         Box::pin(self.clone().handle(request))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryInto;
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::Arc;
+    use std::time::Instant;
+
+    use futures::stream;
+    use http::header::{HeaderMap, CONTENT_ENCODING, CONTENT_LENGTH, HOST, LOCATION};
+    use http::StatusCode;
+    use hyper::{Body, Request};
+    use serde_json::{json, Value};
+
+    use crate::handler::http::encoding::encode_body;
+    use crate::handler::http::rule::Rule;
+    use crate::proxy::http::config::HTTPConfig;
+    use crate::proxy::http::server::{buffer_body, hit_rules, HttpService};
+    use crate::proxy::tcp::connection::ConnectionHandle;
+    use crate::raw_config::RawRule;
+
+    fn rules(rules: Value) -> Vec<Rule> {
+        serde_json::from_value::<Vec<RawRule>>(rules)
+            .unwrap()
+            .into_iter()
+            .map(|rule| rule.try_into().unwrap())
+            .collect()
+    }
+
+    /// new_service returns a service with the rules, whose target refuses connections,
+    /// so the forwarded requests are responded with 502.
+    fn new_service(rules: Vec<Rule>) -> HttpService {
+        let target = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let _client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, remote) = listener.accept().unwrap();
+        let config = HTTPConfig {
+            listen_port: 0,
+            rules,
+            role: None,
+        };
+        HttpService::new(
+            remote,
+            target,
+            Instant::now(),
+            ConnectionHandle::new(&stream).unwrap(),
+            Arc::new(config),
+            None,
+        )
+    }
+
+    fn request() -> Request<Body> {
+        Request::builder().uri("/a").body(Body::empty()).unwrap()
+    }
+
+    #[test]
+    fn test_hit_rules() {
+        let rules = rules(json!([
+            {"name": "missed", "terminal": true, "target": "Request", "selector": {},
+                "actions": {"percent": 0.0}},
+            {"name": "hit", "target": "Request", "selector": {}},
+            {"name": "terminal", "terminal": true, "target": "Request", "selector": {}},
+            {"name": "skipped", "target": "Request", "selector": {}, "limit": {"first": 1}},
+        ]));
+        let hit: Vec<_> = hit_rules(rules.iter().enumerate().collect(), "", "request")
+            .map(|(index, rule)| (index, rule.name.as_deref().unwrap()))
+            .collect();
+        assert_eq!(hit, vec![(1, "hit"), (2, "terminal")]);
+        // the hit of the skipped rule is not counted
+        assert!(rules[3].hit());
+        assert!(!rules[3].hit());
+    }
+
+    #[test]
+    fn test_authority() {
+        let service = new_service(vec![]);
+        let authority = |request: Request<Body>| service.authority(&request).unwrap().unwrap();
+
+        // the absolute URI takes precedence over the `Host` header
+        let absolute = Request::builder()
+            .uri("http://example.com:8080/a")
+            .header(HOST, "example.org")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(authority(absolute), "example.com:8080");
+
+        let relative = Request::builder()
+            .uri("/a")
+            .header(HOST, "example.org")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(authority(relative), "example.org");
+
+        assert_eq!(authority(request()), service.target.to_string().as_str());
+    }
+
+    #[tokio::test]
+    async fn test_handle_terminal() {
+        let service = new_service(rules(json!([
+            {"target": "Response", "selector": {}, "actions": {"replace": {"code": 503}}},
+            {"terminal": true, "target": "Request", "selector": {}, "actions": {}},
+            {"target": "Request", "selector": {}, "actions": {"respond": {"code": 418}}},
+            {"target": "Response", "selector": {}, "actions": {"replace": {"code": 500}}},
+        ])));
+        // the later request rule is skipped so the request is forwarded,
+        // and only the response rule before the terminal one is applied
+        let response = service.handle(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let service = new_service(rules(json!([
+            {"terminal": true, "target": "Request", "selector": {},
+                "actions": {"respond": {"code": 418}}},
+            {"target": "Response", "selector": {}, "actions": {"replace": {"code": 500}}},
+        ])));
+        let response = service.handle(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::IM_A_TEAPOT);
+    }
+
+    #[tokio::test]
+    async fn test_handle_mocked() {
+        let service = new_service(rules(json!([
+            {"target": "Request", "selector": {}, "actions": {
+                "respond": {"code": 200, "body": {"type": "TEXT", "value": "mocked"}}}},
+            {"target": "Request", "selector": {}, "limit": {"first": 1},
+                "actions": {"respond": {"code": 418}}},
+        ])));
+        // the mocked response is returned instead of 502 from the target
+        let response = service.clone().handle(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(body, "mocked");
+        // the later request rule is skipped without counting its hit
+        assert!(service.config.rules[1].hit());
+    }
+
+    #[tokio::test]
+    async fn test_handle_redirect() {
+        let service = new_service(rules(json!([
+            {"target": "Request", "selector": {}, "actions": {"replace": {"path": "/b"}}},
+            {"target": "Request", "selector": {}, "actions": {
+                "redirect": {"code": 307, "location": "https://example.com/v2{path_and_query}"}}},
+        ])));
+        let request = Request::builder()
+            .uri("/a?x=1")
+            .body(Body::empty())
+            .unwrap();
+        let response = service.handle(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+        assert_eq!(response.headers()[LOCATION], "https://example.com/v2/a?x=1");
+    }
+
+    #[tokio::test]
+    async fn test_handle_response_body() {
+        let service = new_service(rules(json!([
+            {"target": "Request", "selector": {"path": "/a"}, "actions": {
+                "replace": {"path": "/b",
+                    "body": {"contents": {"type": "TEXT", "value": "replaced"}}}}},
+            {"target": "Response", "selector": {"path": "/a"}, "actions": {"replace": {"code": 500}}},
+            {"target": "Response", "selector": {"path": "/b",
+                "request_body": {"type": "Contains", "value": "original"}},
+                "actions": {"replace": {"code": 501}}},
+            {"target": "Response", "selector": {"path": "/b",
+                "request_body": {"type": "Contains", "value": "replaced"}},
+                "actions": {"replace": {"code": 503}}},
+        ])));
+        // response rules are matched with the request the actions are applied on
+        let request = Request::builder()
+            .uri("/a")
+            .body(Body::from("original"))
+            .unwrap();
+        let response = service.handle(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn test_handle_body_test() {
+        let body_test = |kind| {
+            json!({"test_as_selector": true, "contents": {"type": "JSONPatch",
+                "value": format!(r#"[{{"op": "test", "path": "/kind", "value": "{}"}}]"#, kind)}})
+        };
+        let service = new_service(rules(json!([
+            {"target": "Request", "selector": {}, "limit": {"first": 1},
+                "actions": {"patch": {"body": body_test("map")}, "respond": {"code": 500}}},
+            {"target": "Request", "selector": {},
+                "actions": {"patch": {"body": body_test("list")}, "respond": {
+                    "code": 200, "body": {"type": "TEXT", "value": r#"{"kind":"map"}"#}}},
+                "response_actions": {"patch": {"body": body_test("list"),
+                    "headers": [["x-correlated", "1"]]}}},
+            {"target": "Response", "selector": {},
+                "actions": {"patch": {"body": body_test("list")}, "replace": {"code": 504}}},
+            {"target": "Response", "selector": {},
+                "actions": {"patch": {"body": body_test("map")}, "replace": {"code": 503}}},
+        ])));
+        // rules and response actions are skipped if the tests fail on the request or response body
+        let listed = Request::builder()
+            .uri("/a")
+            .body(Body::from(r#"{"kind":"list"}"#))
+            .unwrap();
+        let response = service.clone().handle(listed).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(!response.headers().contains_key("x-correlated"));
+        // the hit of the skipped rule is not counted
+        assert!(service.config.rules[0].hit());
+
+        // a missing body is never selected by the tests
+        let response = service.handle(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+    }
+
+    #[tokio::test]
+    async fn test_handle_response_actions() {
+        let service = new_service(rules(json!([
+            {"target": "Request", "selector": {"path": "/a"},
+                "response_actions": {"replace": {"code": 503}}},
+            {"target": "Request", "selector": {}, "actions": {"percent": 0.0},
+                "response_actions": {"replace": {"code": 500}}},
+        ])));
+        // the response actions are applied only if the request rule is hit
+        let response = service.clone().handle(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let other = Request::builder().uri("/b").body(Body::empty()).unwrap();
+        let response = service.handle(other).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+
+        // the response actions of the terminal rule are still applied
+        let service = new_service(rules(json!([
+            {"terminal": true, "target": "Request", "selector": {},
+                "response_actions": {"replace": {"code": 503}}},
+            {"target": "Response", "selector": {}, "actions": {"replace": {"code": 500}}},
+        ])));
+        let response = service.handle(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    async fn read(body: Body) -> Vec<u8> {
+        hyper::body::to_bytes(body).await.unwrap().to_vec()
+    }
+
+    #[tokio::test]
+    async fn test_buffer_body() {
+        let data = br#"{"payment":{"method":"card"}}"#.to_vec();
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_ENCODING, "gzip".parse().unwrap());
+        let encoded = encode_body(&headers, data.clone()).unwrap();
+
+        // the decoded body is returned, and the encoded one is restored
+        let mut body = Body::from(encoded.clone());
+        let buffered = buffer_body(&headers, &mut body, 1024).await.unwrap();
+        assert_eq!(buffered, Some(data));
+        assert_eq!(read(body).await, encoded);
+
+        // the body could not be decoded
+        let mut body = Body::from("not gzip");
+        assert_eq!(buffer_body(&headers, &mut body, 1024).await.unwrap(), None);
+        assert_eq!(read(body).await, b"not gzip");
+    }
+
+    #[tokio::test]
+    async fn test_buffer_body_over_limit() {
+        // the body is not read if `Content-Length` is over the limit
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_LENGTH, 16.into());
+        let mut body = Body::from(vec![b'a'; 16]);
+        assert_eq!(buffer_body(&headers, &mut body, 8).await.unwrap(), None);
+        assert_eq!(read(body).await, vec![b'a'; 16]);
+
+        // the chunks read before reaching the limit are restored
+        let chunks: Vec<Result<_, std::io::Error>> = vec![Ok("aaaa"), Ok("bbbb"), Ok("cccc")];
+        let mut body = Body::wrap_stream(stream::iter(chunks));
+        let headers = HeaderMap::new();
+        assert_eq!(buffer_body(&headers, &mut body, 6).await.unwrap(), None);
+        assert_eq!(read(body).await, b"aaaabbbbcccc");
+
+        // the decoded body is over the limit
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_ENCODING, "gzip".parse().unwrap());
+        let encoded = encode_body(&headers, vec![b'a'; 1024]).unwrap();
+        let mut body = Body::from(encoded.clone());
+        assert_eq!(buffer_body(&headers, &mut body, 512).await.unwrap(), None);
+        assert_eq!(read(body).await, encoded);
+    }
+}
@@ -0,0 +1,60 @@
+use std::io;
+use std::mem::ManuallyDrop;
+use std::net::Shutdown;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::sync::Arc;
+use std::time::Duration;
+
+use socket2::Socket;
+
+/// ConnectionHandle owns a duplicate of the socket of an accepted connection.
+/// It could tear down the connection while the stream is owned by hyper,
+/// the socket is closed once both the stream and all the handles are dropped.
+#[derive(Debug, Clone)]
+pub struct ConnectionHandle {
+    socket: Arc<Socket>,
+}
+
+impl ConnectionHandle {
+    pub fn new<S: AsRawFd>(stream: &S) -> io::Result<Self> {
+        // the socket is borrowed from the stream, it must not be closed here
+        let borrowed = ManuallyDrop::new(unsafe { Socket::from_raw_fd(stream.as_raw_fd()) });
+        Ok(Self {
+            socket: Arc::new(borrowed.try_clone()?),
+        })
+    }
+
+    /// reset sets SO_LINGER to zero, the connection would send TCP RST once it's closed.
+    pub fn reset(&self) -> io::Result<()> {
+        self.socket.set_linger(Some(Duration::from_secs(0)))
+    }
+
+    /// close shuts down both reading and writing of the connection.
+    pub fn close(&self) -> io::Result<()> {
+        self.socket.shutdown(Shutdown::Both)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{ErrorKind, Read};
+    use std::net::{TcpListener, TcpStream};
+
+    use crate::proxy::tcp::connection::ConnectionHandle;
+
+    #[test]
+    fn test_handle_outlives_stream() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        let handle = ConnectionHandle::new(&stream).unwrap();
+
+        // the handle still refers to the connection after the stream is dropped,
+        // and dropping the last handle closes the socket with TCP RST
+        drop(stream);
+        handle.reset().unwrap();
+        drop(handle);
+        let err = client.read(&mut [0; 1]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ConnectionReset);
+    }
+}
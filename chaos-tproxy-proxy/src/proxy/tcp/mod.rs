@@ -1,2 +1,3 @@
+pub mod connection;
 pub mod listener;
 pub mod transparent_socket;
@@ -1,3 +1,4 @@
+use std::cmp::Reverse;
 use std::collections::HashMap;
 use std::convert::{TryFrom, TryInto};
 use std::net::Ipv4Addr;
@@ -6,8 +7,10 @@ use std::time::Duration;
 use std::{fs, io};
 
 use anyhow::{anyhow, Error};
-use http::header::{HeaderMap, HeaderName};
+use http::header::{HeaderMap, HeaderName, HeaderValue, RETRY_AFTER};
 use http::StatusCode;
+use ipnet::IpNet;
+use regex::bytes::Regex;
 use rustls::OwnedTrustAnchor;
 use rustls_pemfile::{certs, rsa_private_keys};
 use serde::{Deserialize, Serialize};
@@ -16,14 +19,16 @@ use tokio_rustls::webpki;
 use wildmatch::WildMatch;
 
 use crate::handler::http::action::{
-    Actions, PatchAction, PatchBodyAction, PatchBodyActionContents, ReplaceAction,
-    ReplaceBodyAction,
+    Actions, BodyRegex, CloseAction, CookieAction, DelayAction, PatchAction, PatchBodyAction,
+    PatchBodyActionContents, Probability, RedirectAction, ReplaceAction, ReplaceBodyAction,
+    RespondAction, StallAction, StatusCodesAction, ThrottleAction, TrickleAction,
 };
-use crate::handler::http::rule::{Rule, Target};
-use crate::handler::http::selector::Selector;
+use crate::handler::http::json_path::JsonPath;
+use crate::handler::http::rule::{HitLimit, Rule, Schedule, Target};
+use crate::handler::http::selector::{BodySelector, HeaderPredicate, Selector};
 use crate::proxy::http::config::{Config, HTTPConfig, TLSConfig};
 
-#[derive(Debug, Eq, PartialEq, Clone, Deserialize, Serialize, Default)]
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize, Default)]
 pub struct RawConfig {
     pub proxy_ports: Option<String>,
     pub listen_port: u16,
@@ -53,11 +58,54 @@ pub struct TLSRawConfig {
     pub key_file: RawFile,
 }
 
-#[derive(Debug, Eq, PartialEq, Clone, Deserialize, Serialize)]
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
 pub struct RawRule {
+    /// Name of the rule, used to identify the rule in logs.
+    pub name: Option<String>,
+    /// Rules with higher priority would be applied first, 0 by default.
+    /// Rules with the same priority are applied in the declaration order.
+    pub priority: Option<i32>,
+    /// Skip the rules after this one once it's applied, false by default.
+    pub terminal: Option<bool>,
     pub target: RawTarget,
     pub selector: RawSelector,
-    pub actions: RawActions,
+    /// Actions applied on the target.
+    pub actions: Option<RawActions>,
+    /// Actions applied on the matched request, the alias of `actions` for `Request` target.
+    pub request_actions: Option<RawActions>,
+    /// Actions applied on the response of the matched request, only for `Request` target.
+    pub response_actions: Option<RawActions>,
+    pub limit: Option<RawHitLimit>,
+    /// The rule would be active after `start_after` since the proxy started.
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    pub start_after: Option<Duration>,
+    /// The rule would be inactive after `duration` since it's started.
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    pub duration: Option<Duration>,
+    /// Turn the rule on and off periodically since it's started.
+    pub schedule: Option<RawSchedule>,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Deserialize, Serialize)]
+pub struct RawSchedule {
+    // the rule is active for `on`
+    #[serde(with = "humantime_serde")]
+    pub on: Duration,
+    // and then inactive for `off`
+    #[serde(with = "humantime_serde")]
+    pub off: Duration,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Deserialize, Serialize)]
+pub struct RawHitLimit {
+    // only the first N matched requests/responses would be hit
+    pub first: Option<u64>,
+    // only every N-th matched request/response would be hit
+    pub every: Option<u64>,
+    // stop hitting after the rule is hit N times
+    pub max_hits: Option<u64>,
 }
 
 #[derive(Debug, Eq, PartialEq, Clone, Deserialize, Serialize)]
@@ -69,6 +117,13 @@ pub enum RawTarget {
 #[derive(Debug, Eq, PartialEq, Clone, Deserialize, Serialize)]
 pub struct RawSelector {
     pub port: Option<u16>,
+    /// Match host of the absolute request URI, the `Host` header, or the target address if both
+    /// are absent, with wildcard matches, e.g. `*.example.com`. The port is optional, and the host is **case insensitive**.
+    pub host: Option<String>,
+    /// Match the client address with any of the CIDRs, e.g. `10.0.1.0/24`.
+    pub source_cidrs: Option<Vec<String>>,
+    /// Match the target address with any of the CIDRs.
+    pub destination_cidrs: Option<Vec<String>>,
     /// Mathc path of `Uri` with wildcard matches.
     ///
     /// Both relative and absolute URIs contain a path component, though it
@@ -82,20 +137,195 @@ pub struct RawSelector {
     /// ```
     /// [wildcard matches](https://www.wikiwand.com/en/Matching_wildcards)
     pub path: Option<String>,
+    /// Match path of `Uri` with [regex](https://docs.rs/regex/latest/regex/#syntax).
+    pub path_regex: Option<String>,
     pub method: Option<String>,
     pub code: Option<u16>,
     pub request_headers: Option<HashMap<String, String>>,
     pub response_headers: Option<HashMap<String, String>>,
+    /// Match request headers with predicates on their values, e.g. wildcard, regex or presence.
+    pub request_header_matches: Option<HashMap<String, RawHeaderPredicate>>,
+    /// Match response headers with predicates on their values.
+    pub response_header_matches: Option<HashMap<String, RawHeaderPredicate>>,
+    /// Match the request body, the body is decoded as `Content-Encoding` before matching.
+    pub request_body: Option<RawBodySelector>,
+    /// Match query parameters of `Uri`, values support wildcard matches.
+    pub queries: Option<HashMap<String, String>>,
+    /// Match cookies of the request by names, values support wildcard matches.
+    pub cookies: Option<HashMap<String, String>>,
+
+    /// Match all of the nested selectors.
+    pub all_of: Option<Vec<RawSelector>>,
+    /// Match at least one of the nested selectors.
+    pub any_of: Option<Vec<RawSelector>>,
+    /// Exclude the request/response matched with the nested selector.
+    pub not: Option<Box<RawSelector>>,
 }
 
-#[derive(Debug, Eq, PartialEq, Clone, Deserialize, Serialize)]
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
 pub struct RawActions {
+    /// The probability in percent (`[0, 100]`) to apply the actions on a matched target.
+    /// Actions are always applied if it's not provided.
+    pub percent: Option<f64>,
+    /// Seed of the random generator used by `percent`, make the decisions reproducible.
+    pub seed: Option<u64>,
     pub abort: Option<bool>,
-    #[serde(default)]
-    #[serde(with = "humantime_serde")]
-    pub delay: Option<Duration>,
+    pub delay: Option<RawDelay>,
     pub replace: Option<RawReplaceAction>,
     pub patch: Option<RawPatchAction>,
+    pub throttle: Option<RawThrottleAction>,
+    pub trickle: Option<RawTrickleAction>,
+    pub stall: Option<RawStallAction>,
+    pub close: Option<RawCloseAction>,
+    /// Remove headers by names, wildcard matches are supported, e.g. `x-cors-*`.
+    pub remove_headers: Option<Vec<String>>,
+    /// Rename headers in order, from the first name of each pair to the second one.
+    pub rename_headers: Option<Vec<(String, String)>>,
+    /// Edit cookies in the `Cookie` headers of requests, or `Set-Cookie` headers of responses.
+    pub cookies: Option<RawCookieAction>,
+    /// Respond with the mocked response. For `Request` target, the request would not be forwarded,
+    /// and the rest request rules would be skipped.
+    pub respond: Option<RawRespondAction>,
+    /// Respond with a status code chosen by weights, like `respond`.
+    pub status_codes: Option<Vec<RawStatusCode>>,
+    /// Redirect the request like `respond`, only for `Request` target.
+    pub redirect: Option<RawRedirectAction>,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Deserialize, Serialize)]
+pub struct RawRedirectAction {
+    // one of 301, 302, 307 and 308, 302 by default
+    pub code: Option<u16>,
+    // `{path}`, `{query}` and `{path_and_query}` would be replaced with the parts of request URI
+    pub location: String,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Deserialize, Serialize)]
+pub struct RawStatusCode {
+    pub code: u16,
+    // the weight to be chosen, 1 by default
+    pub weight: Option<u32>,
+    // empty body by default
+    pub body: Option<RawRespondBody>,
+    // set `Retry-After` header in seconds, usually for 429 and 503, the fraction is rounded up
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    pub retry_after: Option<Duration>,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Deserialize, Serialize)]
+pub struct RawRespondAction {
+    // status code of the response, 200 by default
+    pub code: Option<u16>,
+    pub headers: Option<HashMap<String, String>>,
+    // empty body by default
+    pub body: Option<RawRespondBody>,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Deserialize, Serialize)]
+#[serde(tag = "type", content = "value")]
+pub enum RawRespondBody {
+    // respond body with text
+    TEXT(String),
+
+    // respond body with base64 encoded data
+    BASE64(String),
+
+    // respond body with the contents, or read from the path
+    FILE(RawFile),
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Deserialize, Serialize)]
+pub struct RawCookieAction {
+    // add cookies, or replace the values if the cookies exist
+    pub add: Option<HashMap<String, String>>,
+    // replace the values of the cookies if they exist
+    pub replace: Option<HashMap<String, String>>,
+    // delete cookies by names, which would be expired in `Set-Cookie`
+    pub delete: Option<Vec<String>>,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Deserialize, Serialize)]
+pub struct RawThrottleAction {
+    // bandwidth in bytes per second
+    pub rate: u64,
+    // max bytes could be sent at once, equal to `rate` by default
+    pub burst: Option<u64>,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Deserialize, Serialize)]
+pub struct RawTrickleAction {
+    // bytes of each chunk
+    pub chunk_size: usize,
+    // pause between chunks
+    #[serde(with = "humantime_serde")]
+    pub interval: Duration,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Deserialize, Serialize)]
+pub struct RawStallAction {
+    // bytes of body sent before stalling
+    pub after_bytes: usize,
+    // stall forever if not provided
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    pub duration: Option<Duration>,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Deserialize, Serialize)]
+#[serde(tag = "type", content = "value")]
+pub enum RawCloseAction {
+    // send the first N bytes of the response body, then close the connection
+    Truncate(usize),
+    // close the connection with TCP RST
+    Reset,
+    // close the connection before sending any response bytes
+    Close,
+}
+
+/// RawDelay is either a fixed duration like `1s`, or a distribution of durations.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum RawDelay {
+    Fixed(#[serde(with = "humantime_serde")] Duration),
+    Distribution(RawDelayDistribution),
+}
+
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+#[serde(tag = "type")]
+pub enum RawDelayDistribution {
+    // uniformly distributed in `[latency - jitter, latency + jitter]`, jitter should not exceed latency
+    Jitter {
+        #[serde(with = "humantime_serde")]
+        latency: Duration,
+        #[serde(with = "humantime_serde")]
+        jitter: Duration,
+    },
+    // uniformly distributed in `[min, max]`
+    Uniform {
+        #[serde(with = "humantime_serde")]
+        min: Duration,
+        #[serde(with = "humantime_serde")]
+        max: Duration,
+    },
+    Normal {
+        #[serde(with = "humantime_serde")]
+        mean: Duration,
+        #[serde(with = "humantime_serde")]
+        stddev: Duration,
+    },
+    // pareto distribution with minimum `scale`, smaller `shape` means longer tail
+    Pareto {
+        #[serde(with = "humantime_serde")]
+        scale: Duration,
+        shape: f64,
+    },
+    // log-normal distribution, `median * e^(sigma * N(0, 1))`
+    LogNormal {
+        #[serde(with = "humantime_serde")]
+        median: Duration,
+        sigma: f64,
+    },
 }
 
 #[derive(Debug, Eq, PartialEq, Clone, Deserialize, Serialize)]
@@ -112,6 +342,11 @@ pub struct RawPatchAction {
 
 #[derive(Debug, Eq, PartialEq, Clone, Deserialize, Serialize)]
 pub struct RawPatchBody {
+    // update `Content-Length` to the length of patched body, true by default
+    pub update_content_length: Option<bool>,
+    // select the target by `test` operations of JSONPatch, the rule is skipped if any of them
+    // fails on the body before any action is applied, false by default
+    pub test_as_selector: Option<bool>,
     // the contents of body patch
     pub contents: RawPatchBodyContents,
 }
@@ -121,6 +356,55 @@ pub struct RawPatchBody {
 pub enum RawPatchBodyContents {
     // merge patch json as [rfc7396](https://tools.ietf.org/html/rfc7396)
     JSON(String),
+
+    // json patch as [rfc6902](https://tools.ietf.org/html/rfc6902)
+    JSONPatch(String),
+
+    // replace all matches of regex `pattern` with `replacement`,
+    // capture groups could be referred as `$1` or `${name}`
+    Regex {
+        pattern: String,
+        replacement: String,
+    },
+
+    // replace all occurrences of `pattern` with `replacement`
+    Literal {
+        pattern: String,
+        replacement: String,
+    },
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Deserialize, Serialize)]
+#[serde(tag = "type", content = "value")]
+pub enum RawHeaderPredicate {
+    // any value of the header is matched with the wildcard pattern
+    Wildcard(String),
+
+    // any value of the header contains a match of the regex
+    Regex(String),
+
+    // the header is present
+    Present,
+
+    // the header is absent
+    Absent,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Deserialize, Serialize)]
+#[serde(tag = "type", content = "value")]
+pub enum RawBodySelector {
+    // any of the values selected by [JSONPath](https://goessner.net/articles/JsonPath/) `path`
+    // equals to `value`, only names, indexes, wildcards and descendants are supported in `path`
+    JSONPath {
+        path: String,
+        value: serde_json::Value,
+    },
+
+    // the body contains the substring
+    Contains(String),
+
+    // the body contains a match of the regex
+    Regex(String),
 }
 
 #[derive(Debug, Eq, PartialEq, Clone, Deserialize, Serialize)]
@@ -135,6 +419,8 @@ pub struct RawReplaceAction {
 
 #[derive(Debug, Eq, PartialEq, Clone, Deserialize, Serialize)]
 pub struct RawReplaceBody {
+    // update `Content-Length` to the length of new body, true by default
+    pub update_content_length: Option<bool>,
     // the contents of body patch
     pub contents: RawReplaceBodyContents,
 }
@@ -174,6 +460,28 @@ pub(crate) fn try_from_vec(
     .transpose()
 }
 
+pub(crate) fn try_from_header_predicates(
+    t: Option<HashMap<String, RawHeaderPredicate>>,
+) -> Result<Option<Vec<(HeaderName, HeaderPredicate)>>, anyhow::Error> {
+    t.map(|predicates| {
+        predicates
+            .into_iter()
+            .map(|(header, predicate)| Ok((header.parse()?, predicate.try_into()?)))
+            .collect()
+    })
+    .transpose()
+}
+
+pub(crate) fn try_from_cidrs(cidrs: Vec<String>) -> Result<Vec<IpNet>, anyhow::Error> {
+    cidrs
+        .iter()
+        .map(|cidr| {
+            cidr.parse()
+                .map_err(|e| anyhow!("invalid CIDR {}: {}", cidr, e))
+        })
+        .collect()
+}
+
 impl Default for RawFile {
     fn default() -> Self {
         RawFile::Contents(Default::default())
@@ -195,15 +503,18 @@ impl TryFrom<RawConfig> for Config {
     type Error = Error;
 
     fn try_from(raw: RawConfig) -> Result<Self, Self::Error> {
+        let mut rules = raw
+            .rules
+            .into_iter()
+            .map(TryInto::try_into)
+            .collect::<Result<Vec<Rule>, Self::Error>>()?;
+        // the sort is stable, so rules with the same priority keep the declaration order
+        rules.sort_by_key(|rule| Reverse(rule.priority));
         Ok(Self {
             http_config: HTTPConfig {
                 listen_port: raw.listen_port,
                 role: raw.role,
-                rules: raw
-                    .rules
-                    .into_iter()
-                    .map(TryInto::try_into)
-                    .collect::<Result<Vec<_>, Self::Error>>()?,
+                rules,
             },
 
             tls_config: match raw.tls {
@@ -273,14 +584,82 @@ impl TryFrom<RawRule> for Rule {
     type Error = Error;
 
     fn try_from(rule: RawRule) -> Result<Self, Self::Error> {
+        let (actions, response_actions) = match (
+            &rule.target,
+            rule.actions,
+            rule.request_actions,
+            rule.response_actions,
+        ) {
+            (_, Some(_), Some(_), _) => {
+                return Err(anyhow!("`actions` and `request_actions` are exclusive"))
+            }
+            (RawTarget::Response, _, Some(_), _) | (RawTarget::Response, _, _, Some(_)) => {
+                return Err(anyhow!(
+                    "`request_actions` and `response_actions` are only for `Request` target"
+                ))
+            }
+            (_, actions, request_actions, response_actions) => {
+                (actions.or(request_actions), response_actions)
+            }
+        };
+        // the redirect is rendered with the request URI, which is not known by responses
+        let redirect_on_response = match rule.target {
+            RawTarget::Request => response_actions.as_ref(),
+            RawTarget::Response => actions.as_ref(),
+        }
+        .map_or(false, |actions| actions.redirect.is_some());
+        if redirect_on_response {
+            return Err(anyhow!("`redirect` is only for requests"));
+        }
         Ok(Self {
+            name: rule.name,
+            priority: rule.priority.unwrap_or(0),
+            terminal: rule.terminal.unwrap_or(false),
             target: rule.target.into(),
             selector: rule.selector.try_into()?,
-            actions: rule.actions.try_into()?,
+            actions: actions
+                .map(TryInto::try_into)
+                .transpose()?
+                .unwrap_or_default(),
+            response_actions: response_actions.map(TryInto::try_into).transpose()?,
+            limit: rule.limit.map(TryInto::try_into).transpose()?,
+            start_after: rule.start_after,
+            duration: rule.duration,
+            schedule: rule.schedule.map(TryInto::try_into).transpose()?,
         })
     }
 }
 
+impl TryFrom<RawSchedule> for Schedule {
+    type Error = Error;
+
+    fn try_from(raw: RawSchedule) -> Result<Self, Self::Error> {
+        if raw.on.is_zero() {
+            return Err(anyhow!("invalid schedule, `on` should be positive"));
+        }
+        if raw.on.checked_add(raw.off).is_none() {
+            return Err(anyhow!(
+                "invalid schedule, the period of `on` and `off` is too long"
+            ));
+        }
+        Ok(Schedule {
+            on: raw.on,
+            off: raw.off,
+        })
+    }
+}
+
+impl TryFrom<RawHitLimit> for HitLimit {
+    type Error = Error;
+
+    fn try_from(raw: RawHitLimit) -> Result<Self, Self::Error> {
+        if raw.every == Some(0) {
+            return Err(anyhow!("invalid every 0 of hit limit, should be positive"));
+        }
+        Ok(HitLimit::new(raw.first, raw.every, raw.max_hits))
+    }
+}
+
 impl From<RawTarget> for Target {
     fn from(target: RawTarget) -> Self {
         match target {
@@ -296,7 +675,15 @@ impl TryFrom<RawSelector> for Selector {
     fn try_from(raw: RawSelector) -> Result<Self, Self::Error> {
         Ok(Self {
             port: raw.port,
+            source_cidrs: raw.source_cidrs.map(try_from_cidrs).transpose()?,
+            destination_cidrs: raw.destination_cidrs.map(try_from_cidrs).transpose()?,
+            host: raw.host.map(|host| WildMatch::new(&host.to_lowercase())),
             path: raw.path.as_ref().map(|p| WildMatch::new(p)),
+            path_regex: raw
+                .path_regex
+                .as_deref()
+                .map(regex::Regex::new)
+                .transpose()?,
             method: raw
                 .method
                 .as_ref()
@@ -305,6 +692,33 @@ impl TryFrom<RawSelector> for Selector {
             request_headers: try_from_hash_map(raw.request_headers)?,
             code: raw.code.map(StatusCode::from_u16).transpose()?,
             response_headers: try_from_hash_map(raw.response_headers)?,
+            request_header_matches: try_from_header_predicates(raw.request_header_matches)?,
+            response_header_matches: try_from_header_predicates(raw.response_header_matches)?,
+            request_body: raw.request_body.map(TryInto::try_into).transpose()?,
+            queries: raw.queries.map(|queries| {
+                queries
+                    .into_iter()
+                    .map(|(key, value)| (key, WildMatch::new(&value)))
+                    .collect()
+            }),
+            cookies: raw.cookies.map(|cookies| {
+                cookies
+                    .into_iter()
+                    .map(|(name, value)| (name, WildMatch::new(&value)))
+                    .collect()
+            }),
+            all_of: raw
+                .all_of
+                .map(|selectors| selectors.into_iter().map(TryInto::try_into).collect())
+                .transpose()?,
+            any_of: raw
+                .any_of
+                .map(|selectors| selectors.into_iter().map(TryInto::try_into).collect())
+                .transpose()?,
+            not: raw
+                .not
+                .map(|selector| (*selector).try_into().map(Box::new))
+                .transpose()?,
         })
     }
 }
@@ -313,15 +727,162 @@ impl TryFrom<RawActions> for Actions {
     type Error = Error;
 
     fn try_from(raw: RawActions) -> Result<Self, Self::Error> {
+        let mocked = [
+            raw.respond.is_some(),
+            raw.status_codes.is_some(),
+            raw.redirect.is_some(),
+        ];
+        if mocked.iter().filter(|m| **m).count() > 1 {
+            return Err(anyhow!(
+                "`respond`, `status_codes` and `redirect` are exclusive"
+            ));
+        }
+        let probability = match raw.percent {
+            Some(percent) if !(0.0..=100.0).contains(&percent) => {
+                return Err(anyhow!(
+                    "invalid percent {}, should be in [0, 100]",
+                    percent
+                ));
+            }
+            Some(percent) => Some(Probability::new(percent, raw.seed)),
+            None if raw.seed.is_some() => {
+                return Err(anyhow!("`seed` is only for `percent`"));
+            }
+            None => None,
+        };
         Ok(Self {
+            probability,
             abort: raw.abort.unwrap_or(false),
-            delay: raw.delay,
+            delay: raw.delay.map(TryInto::try_into).transpose()?,
             replace: raw.replace.map(TryInto::try_into).transpose()?,
             patch: raw.patch.map(TryInto::try_into).transpose()?,
+            throttle: raw.throttle.map(TryInto::try_into).transpose()?,
+            trickle: raw.trickle.map(TryInto::try_into).transpose()?,
+            stall: raw.stall.map(Into::into),
+            close: raw.close.map(Into::into),
+            remove_headers: raw.remove_headers.map(|names| {
+                names
+                    .iter()
+                    .map(|name| WildMatch::new(&name.to_ascii_lowercase()))
+                    .collect()
+            }),
+            rename_headers: raw
+                .rename_headers
+                .map(|renames| {
+                    renames
+                        .into_iter()
+                        .map(|(from, to)| Ok((from.parse()?, to.parse()?)))
+                        .collect::<Result<Vec<_>, Self::Error>>()
+                })
+                .transpose()?,
+            cookies: raw.cookies.map(TryInto::try_into).transpose()?,
+            respond: raw.respond.map(TryInto::try_into).transpose()?,
+            status_codes: raw.status_codes.map(TryInto::try_into).transpose()?,
+            redirect: raw.redirect.map(TryInto::try_into).transpose()?,
+        })
+    }
+}
+
+impl TryFrom<RawDelay> for DelayAction {
+    type Error = Error;
+
+    fn try_from(raw: RawDelay) -> Result<Self, Self::Error> {
+        let distribution = match raw {
+            RawDelay::Fixed(delay) => return Ok(DelayAction::Fixed(delay)),
+            RawDelay::Distribution(distribution) => distribution,
+        };
+        Ok(match distribution {
+            RawDelayDistribution::Jitter { latency, jitter } => {
+                if jitter > latency {
+                    return Err(anyhow!(
+                        "invalid jitter delay, jitter {:?} > latency {:?}",
+                        jitter,
+                        latency
+                    ));
+                }
+                DelayAction::Jitter { latency, jitter }
+            }
+            RawDelayDistribution::Uniform { min, max } => {
+                if min > max {
+                    return Err(anyhow!(
+                        "invalid uniform delay, min {:?} > max {:?}",
+                        min,
+                        max
+                    ));
+                }
+                DelayAction::Uniform { min, max }
+            }
+            RawDelayDistribution::Normal { mean, stddev } => DelayAction::Normal { mean, stddev },
+            RawDelayDistribution::Pareto { scale, shape } => {
+                if shape.is_nan() || shape <= 0.0 {
+                    return Err(anyhow!(
+                        "invalid pareto delay, shape {} should be positive",
+                        shape
+                    ));
+                }
+                DelayAction::Pareto { scale, shape }
+            }
+            RawDelayDistribution::LogNormal { median, sigma } => {
+                if sigma.is_nan() || sigma < 0.0 {
+                    return Err(anyhow!(
+                        "invalid log-normal delay, sigma {} should not be negative",
+                        sigma
+                    ));
+                }
+                DelayAction::LogNormal { median, sigma }
+            }
+        })
+    }
+}
+
+impl TryFrom<RawThrottleAction> for ThrottleAction {
+    type Error = Error;
+
+    fn try_from(raw: RawThrottleAction) -> Result<Self, Self::Error> {
+        let burst = raw.burst.unwrap_or(raw.rate);
+        if raw.rate == 0 || burst == 0 {
+            return Err(anyhow!("throttle rate and burst should be positive"));
+        }
+        Ok(Self {
+            rate: raw.rate,
+            burst,
         })
     }
 }
 
+impl TryFrom<RawTrickleAction> for TrickleAction {
+    type Error = Error;
+
+    fn try_from(raw: RawTrickleAction) -> Result<Self, Self::Error> {
+        if raw.chunk_size == 0 {
+            return Err(anyhow!("trickle chunk_size should be positive"));
+        }
+        Ok(Self {
+            chunk_size: raw.chunk_size,
+            interval: raw.interval,
+        })
+    }
+}
+
+impl From<RawStallAction> for StallAction {
+    fn from(raw: RawStallAction) -> Self {
+        Self {
+            after_bytes: raw.after_bytes,
+            duration: raw.duration,
+        }
+    }
+}
+
+impl From<RawCloseAction> for CloseAction {
+    fn from(raw: RawCloseAction) -> Self {
+        match raw {
+            RawCloseAction::Truncate(n) => CloseAction::Truncate(n),
+            RawCloseAction::Reset => CloseAction::Reset,
+            RawCloseAction::Close => CloseAction::Close,
+        }
+    }
+}
+
 impl TryFrom<RawPatchAction> for PatchAction {
     type Error = Error;
 
@@ -334,6 +895,77 @@ impl TryFrom<RawPatchAction> for PatchAction {
     }
 }
 
+impl TryFrom<RawCookieAction> for CookieAction {
+    type Error = Error;
+
+    fn try_from(raw: RawCookieAction) -> Result<Self, Self::Error> {
+        let invalid = |s: &str, separators: &[char]| {
+            s.chars()
+                .any(|c| c.is_ascii_control() || c.is_whitespace() || separators.contains(&c))
+        };
+        let check_name = |name: String| {
+            if name.is_empty() || invalid(&name, &['=', ';', ',']) {
+                return Err(anyhow!("invalid cookie name {:?}", name));
+            }
+            Ok(name)
+        };
+        let pairs = |cookies: Option<HashMap<String, String>>| {
+            cookies
+                .into_iter()
+                .flatten()
+                .map(|(name, value)| {
+                    if invalid(&value, &[';', ',', '"', '\\']) {
+                        return Err(anyhow!("invalid value {:?} of cookie {}", value, name));
+                    }
+                    Ok((check_name(name)?, value))
+                })
+                .collect::<Result<Vec<_>, Self::Error>>()
+        };
+        Ok(Self {
+            add: pairs(raw.add)?,
+            replace: pairs(raw.replace)?,
+            delete: raw
+                .delete
+                .into_iter()
+                .flatten()
+                .map(check_name)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+impl TryFrom<RawHeaderPredicate> for HeaderPredicate {
+    type Error = Error;
+
+    fn try_from(raw: RawHeaderPredicate) -> Result<Self, Self::Error> {
+        Ok(match raw {
+            RawHeaderPredicate::Wildcard(pattern) => {
+                HeaderPredicate::Wildcard(WildMatch::new(&pattern))
+            }
+            RawHeaderPredicate::Regex(pattern) => HeaderPredicate::Regex(Regex::new(&pattern)?),
+            RawHeaderPredicate::Present => HeaderPredicate::Present,
+            RawHeaderPredicate::Absent => HeaderPredicate::Absent,
+        })
+    }
+}
+
+impl TryFrom<RawBodySelector> for BodySelector {
+    type Error = Error;
+
+    fn try_from(raw: RawBodySelector) -> Result<Self, Self::Error> {
+        match raw {
+            RawBodySelector::JSONPath { path, value } => Ok(BodySelector::JSONPath {
+                path: JsonPath::parse(&path)?,
+                value,
+            }),
+            RawBodySelector::Contains(substring) => {
+                Ok(BodySelector::Regex(Regex::new(&regex::escape(&substring))?))
+            }
+            RawBodySelector::Regex(pattern) => Ok(BodySelector::Regex(Regex::new(&pattern)?)),
+        }
+    }
+}
+
 impl TryFrom<RawPatchBodyContents> for PatchBodyActionContents {
     type Error = Error;
 
@@ -342,6 +974,23 @@ impl TryFrom<RawPatchBodyContents> for PatchBodyActionContents {
             RawPatchBodyContents::JSON(ref raw) => {
                 Ok(PatchBodyActionContents::JSON(serde_json::from_str(raw)?))
             }
+            RawPatchBodyContents::JSONPatch(ref raw) => Ok(PatchBodyActionContents::JSONPatch(
+                serde_json::from_str(raw)?,
+            )),
+            RawPatchBodyContents::Regex {
+                pattern,
+                replacement,
+            } => Ok(PatchBodyActionContents::Regex {
+                regex: BodyRegex(Regex::new(&pattern)?),
+                replacement: replacement.into_bytes(),
+            }),
+            RawPatchBodyContents::Literal {
+                pattern,
+                replacement,
+            } => Ok(PatchBodyActionContents::Regex {
+                regex: BodyRegex(Regex::new(&regex::escape(&pattern))?),
+                replacement: replacement.replace('$', "$$").into_bytes(),
+            }),
         }
     }
 }
@@ -352,6 +1001,8 @@ impl TryFrom<RawPatchBody> for PatchBodyAction {
     fn try_from(raw: RawPatchBody) -> Result<Self, Self::Error> {
         Ok(Self {
             contents: raw.contents.try_into()?,
+            update_content_length: raw.update_content_length.unwrap_or(true),
+            test_as_selector: raw.test_as_selector.unwrap_or(false),
         })
     }
 }
@@ -365,6 +1016,91 @@ impl TryFrom<RawReplaceBody> for ReplaceBodyAction {
                 RawReplaceBodyContents::TEXT(text) => text.into_bytes(),
                 RawReplaceBodyContents::BASE64(encoded) => base64::decode(encoded)?,
             },
+            update_content_length: raw.update_content_length.unwrap_or(true),
+        })
+    }
+}
+
+impl TryFrom<RawRespondAction> for RespondAction {
+    type Error = Error;
+
+    fn try_from(raw: RawRespondAction) -> Result<Self, Self::Error> {
+        Ok(Self {
+            code: raw
+                .code
+                .map(StatusCode::from_u16)
+                .transpose()?
+                .unwrap_or(StatusCode::OK),
+            headers: try_from_hash_map(raw.headers)?.unwrap_or_default(),
+            body: match raw.body {
+                None => vec![],
+                Some(RawRespondBody::TEXT(text)) => text.into_bytes(),
+                Some(RawRespondBody::BASE64(encoded)) => base64::decode(encoded)?,
+                Some(RawRespondBody::FILE(file)) => file.try_into()?,
+            }
+            .into(),
+        })
+    }
+}
+
+impl TryFrom<Vec<RawStatusCode>> for StatusCodesAction {
+    type Error = Error;
+
+    fn try_from(raw: Vec<RawStatusCode>) -> Result<Self, Self::Error> {
+        if raw.is_empty() {
+            return Err(anyhow!("status codes should not be empty"));
+        }
+        let choices = raw
+            .into_iter()
+            .map(|status| {
+                let weight = status.weight.unwrap_or(1);
+                if weight == 0 {
+                    return Err(anyhow!("invalid weight 0 of status code {}", status.code));
+                }
+                let mut headers = HashMap::new();
+                if let Some(retry_after) = status.retry_after {
+                    // round up, so a sub-second delay is not sent as `Retry-After: 0`
+                    let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+                    headers.insert(RETRY_AFTER.to_string(), secs.to_string());
+                }
+                let respond = RawRespondAction {
+                    code: Some(status.code),
+                    headers: Some(headers),
+                    body: status.body,
+                };
+                Ok((weight, respond.try_into()?))
+            })
+            .collect::<Result<Vec<_>, Self::Error>>()?;
+        if choices
+            .iter()
+            .try_fold(0u32, |total, (weight, _)| total.checked_add(*weight))
+            .is_none()
+        {
+            return Err(anyhow!("total weight of status codes overflows"));
+        }
+        Ok(Self { choices })
+    }
+}
+
+impl TryFrom<RawRedirectAction> for RedirectAction {
+    type Error = Error;
+
+    fn try_from(raw: RawRedirectAction) -> Result<Self, Self::Error> {
+        let code = match raw.code {
+            None => StatusCode::FOUND,
+            Some(code @ (301 | 302 | 307 | 308)) => StatusCode::from_u16(code)?,
+            Some(code) => {
+                return Err(anyhow!(
+                    "invalid redirect code {}, should be one of 301, 302, 307 and 308",
+                    code
+                ))
+            }
+        };
+        // the rendered parts of request URI are always valid in header values
+        HeaderValue::from_str(&raw.location)?;
+        Ok(Self {
+            code,
+            location: raw.location,
         })
     }
 }
@@ -387,3 +1123,169 @@ impl TryFrom<RawReplaceAction> for ReplaceAction {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+    use std::io::Write;
+    use std::time::Duration;
+
+    use http::header::RETRY_AFTER;
+    use http::StatusCode;
+    use serde_json::{json, Value};
+    use tempfile::NamedTempFile;
+
+    use crate::handler::http::action::{Actions, RespondAction, StatusCodesAction};
+    use crate::handler::http::rule::Rule;
+    use crate::proxy::http::config::Config;
+    use crate::raw_config::{
+        RawActions, RawConfig, RawFile, RawRespondAction, RawRespondBody, RawRule, RawStatusCode,
+    };
+
+    fn raw_rule(rule: Value) -> RawRule {
+        serde_json::from_value(rule).unwrap()
+    }
+
+    #[test]
+    fn test_config_rules_priority() {
+        let raw = RawConfig {
+            rules: vec![
+                raw_rule(json!({"name": "a", "target": "Request", "selector": {}})),
+                raw_rule(json!({"name": "b", "priority": -1, "target": "Request", "selector": {}})),
+                raw_rule(
+                    json!({"name": "c", "priority": 10, "target": "Response", "selector": {}}),
+                ),
+                raw_rule(json!({"name": "d", "priority": 0, "target": "Response", "selector": {}})),
+                raw_rule(json!({"name": "e", "priority": 10, "target": "Request", "selector": {}})),
+            ],
+            ..Default::default()
+        };
+        let config = Config::try_from(raw).unwrap();
+        let names: Vec<_> = config
+            .http_config
+            .rules
+            .iter()
+            .map(|rule| rule.name.as_deref().unwrap())
+            .collect();
+        // higher priority first, and the declaration order is kept for the same priority
+        assert_eq!(names, vec!["c", "e", "a", "d", "b"]);
+    }
+
+    #[test]
+    fn test_respond_body_file() {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(br#"{"message": "unavailable"}"#).unwrap();
+        let respond = |body| RawRespondAction {
+            code: Some(503),
+            headers: None,
+            body: Some(RawRespondBody::FILE(body)),
+        };
+
+        let action = RespondAction::try_from(respond(RawFile::Path(file.path().into()))).unwrap();
+        assert_eq!(action.code, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(action.body, &br#"{"message": "unavailable"}"#[..]);
+
+        let action =
+            RespondAction::try_from(respond(RawFile::Contents(b"mocked".to_vec()))).unwrap();
+        assert_eq!(action.body, "mocked");
+
+        let missing = file.path().with_extension("missing");
+        assert!(RespondAction::try_from(respond(RawFile::Path(missing))).is_err());
+    }
+
+    #[test]
+    fn test_status_codes_retry_after() {
+        let status_codes = |retry_after| {
+            vec![RawStatusCode {
+                code: 503,
+                weight: None,
+                body: None,
+                retry_after: Some(retry_after),
+            }]
+        };
+        for (retry_after, secs) in [
+            (Duration::from_secs(5), "5"),
+            (Duration::from_millis(1500), "2"),
+            (Duration::from_millis(200), "1"),
+        ] {
+            let action = StatusCodesAction::try_from(status_codes(retry_after)).unwrap();
+            assert_eq!(action.choices[0].1.headers[RETRY_AFTER], secs);
+        }
+    }
+
+    #[test]
+    fn test_rule_actions() {
+        let rule = raw_rule(json!({
+            "target": "Request",
+            "selector": {},
+            "request_actions": {"abort": true},
+            "response_actions": {"delay": "1s"}
+        }));
+        let rule = Rule::try_from(rule).unwrap();
+        assert!(rule.actions.abort);
+        assert!(rule.response_actions.is_some());
+
+        let invalid = [
+            json!({
+                "target": "Request",
+                "selector": {},
+                "actions": {"abort": true},
+                "request_actions": {"abort": true}
+            }),
+            json!({"target": "Response", "selector": {}, "request_actions": {"abort": true}}),
+            json!({"target": "Response", "selector": {}, "response_actions": {"abort": true}}),
+        ];
+        for rule in invalid {
+            assert!(Rule::try_from(raw_rule(rule)).is_err());
+        }
+    }
+
+    #[test]
+    fn test_rule_schedule() {
+        let rule = |on, off| {
+            Rule::try_from(raw_rule(json!({
+                "target": "Request",
+                "selector": {},
+                "schedule": {"on": on, "off": off}
+            })))
+        };
+        let schedule = rule("1s", "2s").unwrap().schedule.unwrap();
+        assert_eq!(schedule.on, Duration::from_secs(1));
+        assert_eq!(schedule.off, Duration::from_secs(2));
+
+        assert!(rule("0s", "2s").is_err());
+        // the period of the schedule overflows
+        assert!(rule("18446744073709551615s", "1s").is_err());
+    }
+
+    #[test]
+    fn test_actions_percent() {
+        let actions =
+            |actions| Actions::try_from(serde_json::from_value::<RawActions>(actions).unwrap());
+        let probability = actions(json!({"percent": 50.0, "seed": 42}))
+            .unwrap()
+            .probability
+            .unwrap();
+        assert_eq!(probability.percent, 50.0);
+        assert_eq!(probability.seed, Some(42));
+        assert!(actions(json!({})).unwrap().probability.is_none());
+
+        assert!(actions(json!({"percent": 100.5})).is_err());
+        // the seed is meaningless without percent
+        assert!(actions(json!({"seed": 42})).is_err());
+    }
+
+    #[test]
+    fn test_actions_rename_headers() {
+        let raw: RawActions = serde_json::from_value(json!({
+            "rename_headers": [["x-b", "x-c"], ["x-a", "x-b"]]
+        }))
+        .unwrap();
+        let renames = Actions::try_from(raw).unwrap().rename_headers.unwrap();
+        let names: Vec<_> = renames
+            .iter()
+            .map(|(from, to)| (from.as_str(), to.as_str()))
+            .collect();
+        assert_eq!(names, vec![("x-b", "x-c"), ("x-a", "x-b")]);
+    }
+}
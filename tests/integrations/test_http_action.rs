@@ -20,8 +20,6 @@ async fn test_http_content_length_replace() {
         (data.len() - 2).to_string().parse().unwrap(),
     );
     let actions = Actions {
-        abort: false,
-        delay: None,
         replace: Some(ReplaceAction {
             path: None,
             method: None,
@@ -30,7 +28,7 @@ async fn test_http_content_length_replace() {
             queries: None,
             headers: Some(headers),
         }),
-        patch: None,
+        ..Default::default()
     };
 
     let req = apply_request_action(req, &actions).await.unwrap();